@@ -3,7 +3,7 @@ use egui_graphs::{
     SettingsStyle,
 };
 use relatable::{
-    petgraph::{self, algo::{dijkstra, Measure}, csr::DefaultIx, data::DataMap, visit::{depth_first_search, Bfs, DfsEvent, EdgeFiltered, EdgeRef, IntoEdgeReferences, IntoNodeReferences, NodeRef, Walker}, Directed},
+    petgraph::{csr::DefaultIx, visit::{EdgeRef, IntoEdgeReferences, IntoNodeReferences, NodeRef}, Directed},
     HashSetGraph, Relation, TagGraphNode,
 };
 
@@ -14,7 +14,7 @@ pub struct TemplateApp {
 
 impl TemplateApp {
     /// Called once before the first frame.
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
         // This is also where you can customize the look and feel of egui using
         // `cc.egui_ctx.set_visuals` and `cc.egui_ctx.set_fonts`.
 
@@ -22,13 +22,7 @@ impl TemplateApp {
         let mut graph: Graph<TagGraphNode, Relation, Directed, DefaultIx, DefaultNodeShape, DefaultEdgeShape> = (&relatable_graph.graph).into();
 
         for (index, weight) in relatable_graph.graph.node_references() {
-            graph.node_mut(index).unwrap().set_label(match weight{
-                TagGraphNode::File { path } => path.file_name().expect("a file node should have a filename").to_string_lossy().to_string(),
-                TagGraphNode::Directory { path } => format!("{}/", path.file_name().expect("a directory node should have a name").to_string_lossy()),
-                TagGraphNode::RootDirectory => "ROOT_DIR".to_string(),
-                TagGraphNode::RootTag => "ROOT_TAG".to_string(),
-                TagGraphNode::Tag(t) => format!("[{}]", t),
-            });
+            graph.node_mut(index).unwrap().set_label(relatable::node_label(weight));
         }
 
         for e in relatable_graph.graph.edge_references() {
@@ -37,7 +31,7 @@ impl TemplateApp {
 
 
         TemplateApp {
-            graph: graph,
+            graph,
             relatable_graph,
         }
     }
@@ -52,26 +46,15 @@ impl eframe::App for TemplateApp {
         egui::TopBottomPanel::bottom("bottom_panel").show(ctx, |ui| {
             for node in self.graph.selected_nodes() {
                 ui.label(format!("node {:?}", node.id()));
-                let data = self.relatable_graph.graph.node_weight(*node);
                 ui.label(format!("node {}", node.index()));
                 
                 // Get all the tags assigned to the selected node
-                let tag_graph = EdgeFiltered::from_fn(&self.relatable_graph.graph, |edge| {
-                    match edge.weight(){
-                        Relation::Parent => true,
-                        Relation::HasTag => true,
-                        Relation::TagAssignedTo => false,
-                        Relation::Child => false
+                let tags = match &self.relatable_graph.graph[*node] {
+                    TagGraphNode::File { path } | TagGraphNode::Directory { path } | TagGraphNode::Symlink { path, .. } => {
+                        relatable::get_tags_for_path(&self.relatable_graph, path)
                     }
-                });
-
-                let mut tags = vec![];
-                let mut bfs = Bfs::new(&tag_graph, *node);
-                while let Some(n) = bfs.next(&tag_graph) {
-                    if let TagGraphNode::Tag(tag) = &self.relatable_graph.graph[n]{
-                        tags.push(tag.clone());
-                    }
-                }
+                    _ => vec![],
+                };
 
                 ui.label(tags.join(", "));
                 
@@ -101,17 +84,3 @@ impl eframe::App for TemplateApp {
         });
     }
 }
-
-fn powered_by_egui_and_eframe(ui: &mut egui::Ui) {
-    ui.horizontal(|ui| {
-        ui.spacing_mut().item_spacing.x = 0.0;
-        ui.label("Powered by ");
-        ui.hyperlink_to("egui", "https://github.com/emilk/egui");
-        ui.label(" and ");
-        ui.hyperlink_to(
-            "eframe",
-            "https://github.com/emilk/egui/tree/master/crates/eframe",
-        );
-        ui.label(".");
-    });
-}
@@ -0,0 +1,114 @@
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use clap::{Args, Parser, Subcommand};
+
+/// Query the tags of files from the shell
+#[derive(Parser)]
+#[command(name = "relatable", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the tags of a file or directory, including inherited ones
+    Tags {
+        path: PathBuf,
+        /// The directory to read tag files from; can be given more than once
+        #[arg(long = "root", default_value = ".")]
+        roots: Vec<PathBuf>,
+    },
+    /// List the files and directories with a tag
+    Files {
+        tag: String,
+        /// The directory to read tag files from; can be given more than once
+        #[arg(long = "root", default_value = ".")]
+        roots: Vec<PathBuf>,
+        /// Also list everything inside tagged directories
+        #[arg(long)]
+        contained: bool,
+    },
+    /// Print the whole tag graph of a directory
+    Graph {
+        #[command(flatten)]
+        format: GraphFormat,
+        #[arg(required = true)]
+        roots: Vec<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct GraphFormat {
+    /// Graphviz DOT, e.g. for `dot -Tsvg`
+    #[arg(long)]
+    dot: bool,
+    /// JSON lists of nodes and edges
+    #[arg(long)]
+    json: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let cli = Cli::parse();
+
+    match run(cli.command, &mut io::stdout().lock()) {
+        // Output piped into something like `head` that stopped reading
+        Err(e)
+            if e.downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+fn run(command: Command, out: &mut impl Write) -> anyhow::Result<()> {
+    match command {
+        Command::Tags { path, roots } => {
+            let graph = tagged_files(&roots)?;
+            let path = path
+                .canonicalize()
+                .with_context(|| format!("couldn't find {:?}", path))?;
+            for tag in relatable::get_tags_for_path(&graph, &path) {
+                writeln!(out, "{}", tag)?;
+            }
+        }
+        Command::Files {
+            tag,
+            roots,
+            contained,
+        } => {
+            let graph = tagged_files(&roots)?;
+            for path in relatable::files_with_tag(&graph, &tag, contained) {
+                writeln!(out, "{}", path.display())?;
+            }
+        }
+        Command::Graph { format, roots } => {
+            let graph = tagged_files(&roots)?;
+            if format.dot {
+                write!(
+                    out,
+                    "{}",
+                    relatable::export::to_dot(&graph, &relatable::export::DotOptions::default())
+                )?;
+            } else if format.json {
+                writeln!(out, "{}", relatable::to_json(&graph)?)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn tagged_files(
+    roots: &[PathBuf],
+) -> anyhow::Result<relatable::DirectedHashSetGraph<relatable::TagGraphNode, relatable::Relation>> {
+    relatable::get_tagged_files_multi(roots)
+        .with_context(|| format!("couldn't read the tags under {:?}", roots))
+}
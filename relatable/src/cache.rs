@@ -0,0 +1,134 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use log::{trace, warn};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::{
+    canonicalize_dir, outermost, rescan_subtree_with, scan, tagfile::write_contents_atomic,
+    DirectedHashSetGraph, Error, Relation, ScanOptions, TagGraphNode,
+};
+
+/// The version of the cache file format. Bump it whenever `TagGraphNode` or `Relation` change, so older
+/// caches are scanned again instead of being misread.
+pub const CACHE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Cache {
+    version: u32,
+    root: PathBuf,
+    /// Modification times of the directories and tag files under the root, from `snapshot`
+    modified: HashMap<PathBuf, SystemTime>,
+    graph: DirectedHashSetGraph<TagGraphNode, Relation>,
+}
+
+/// Like `scan`, but keeps the graph in a cache file at `cache_path` between runs. Directories that haven't
+/// changed since the cache was written are loaded from it, and only the ones that have are scanned again,
+/// like `rescan_subtree`. A cache that's missing, unreadable, for another root or from another
+/// `CACHE_VERSION` is replaced by a full scan. Pass the same `options` every time, since the cache doesn't
+/// remember them.
+/// Tags that nothing has any more after a rescan are kept until `prune_orphan_tags` removes them. Failing to
+/// write the cache is only logged.
+pub fn load_or_scan(
+    root: impl AsRef<Path>,
+    options: &ScanOptions,
+    cache_path: impl AsRef<Path>,
+) -> Result<DirectedHashSetGraph<TagGraphNode, Relation>, Error> {
+    let root = canonicalize_dir(root.as_ref())?;
+    let cache_path = cache_path.as_ref();
+    // Taken before scanning, so anything changed during the scan is scanned again next time
+    let modified = snapshot(&root, options);
+
+    let graph = match read_cache(cache_path, &root) {
+        Some(mut cache) => {
+            let changed = changed_dirs(&root, &cache.modified, &modified, options);
+            trace!("Rescanning {:?} from the cache", changed);
+            for dir in outermost(&changed) {
+                rescan_subtree_with(&mut cache.graph, &root, dir, options)?;
+            }
+            cache.graph
+        }
+        None => scan(&root, options)?.0,
+    };
+
+    let cache = Cache {
+        version: CACHE_VERSION,
+        root,
+        modified,
+        graph,
+    };
+    if let Err(e) = write_cache(cache_path, &cache) {
+        warn!("Couldn't write the cache {:?}: {}", cache_path, e);
+    }
+    Ok(cache.graph)
+}
+
+/// Gets the modification times of the directories and tag files under a root.
+/// A directory's changes when something in it is created, removed or renamed.
+fn snapshot(root: &Path, options: &ScanOptions) -> HashMap<PathBuf, SystemTime> {
+    WalkDir::new(root)
+        .into_iter()
+        .flatten()
+        .filter(|e| e.file_type().is_dir() || options.is_tagfile(e.path()))
+        .filter_map(|e| {
+            let modified = e.metadata().ok()?.modified().ok()?;
+            Some((e.into_path(), modified))
+        })
+        .collect()
+}
+
+/// Gets the directories whose contents or tag files changed between two snapshots
+fn changed_dirs(
+    root: &Path,
+    before: &HashMap<PathBuf, SystemTime>,
+    after: &HashMap<PathBuf, SystemTime>,
+    options: &ScanOptions,
+) -> BTreeSet<PathBuf> {
+    after
+        .iter()
+        .filter(|(path, time)| before.get(*path) != Some(*time))
+        .map(|(path, _)| path)
+        .chain(before.keys().filter(|path| !after.contains_key(*path)))
+        .filter_map(|path| {
+            // A tag file changes its directory, and a directory that's gone changes its parent
+            if options.is_tagfile(path) || !after.contains_key(path) {
+                path.parent()
+            } else {
+                Some(path.as_path())
+            }
+        })
+        .filter(|dir| dir.starts_with(root))
+        .map(Path::to_path_buf)
+        .collect()
+}
+
+fn read_cache(cache_path: &Path, root: &Path) -> Option<Cache> {
+    let bytes = fs::read(cache_path).ok()?;
+    // The version comes first, so it can be checked before trying to read the rest in whatever layout it has
+    let version: u32 = bincode::deserialize(&bytes).ok()?;
+    if version != CACHE_VERSION {
+        trace!("The cache {:?} is from version {}", cache_path, version);
+        return None;
+    }
+    match bincode::deserialize::<Cache>(&bytes) {
+        Ok(cache) if cache.root == root => Some(cache),
+        Ok(_) => {
+            trace!("The cache {:?} is for another root", cache_path);
+            None
+        }
+        Err(e) => {
+            warn!("Couldn't read the cache {:?}: {}", cache_path, e);
+            None
+        }
+    }
+}
+
+fn write_cache(cache_path: &Path, cache: &Cache) -> Result<(), Error> {
+    let bytes = bincode::serialize(cache)?;
+    write_contents_atomic(cache_path, &bytes, false)
+}
@@ -0,0 +1,63 @@
+use std::collections::HashSet;
+
+use petgraph::Directed;
+
+use crate::{HashSetGraph, Relation, TagGraphNode};
+
+/// What changed between two graphs, e.g. two scans of the same root.
+/// Everything is sorted, so the same two graphs always give the same diff however they were built.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GraphDiff {
+    pub added_nodes: Vec<TagGraphNode>,
+    pub removed_nodes: Vec<TagGraphNode>,
+    /// `(source, relation, target)` edges
+    pub added_edges: Vec<(TagGraphNode, Relation, TagGraphNode)>,
+    pub removed_edges: Vec<(TagGraphNode, Relation, TagGraphNode)>,
+}
+
+impl GraphDiff {
+    /// Checks whether the graphs were the same
+    pub fn is_empty(&self) -> bool {
+        self.added_nodes.is_empty()
+            && self.removed_nodes.is_empty()
+            && self.added_edges.is_empty()
+            && self.removed_edges.is_empty()
+    }
+}
+
+/// Compares two graphs. Nodes are matched up by their weight, so the node indices of the two graphs
+/// don't need to agree.
+pub fn diff(
+    old: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    new: &HashSetGraph<TagGraphNode, Relation, Directed>,
+) -> GraphDiff {
+    let old_nodes: HashSet<&TagGraphNode> = old.iter_nodes().collect();
+    let new_nodes: HashSet<&TagGraphNode> = new.iter_nodes().collect();
+    let old_edges: HashSet<(&TagGraphNode, &Relation, &TagGraphNode)> =
+        old.iter_edges().map(|(a, b, e)| (a, e, b)).collect();
+    let new_edges: HashSet<(&TagGraphNode, &Relation, &TagGraphNode)> =
+        new.iter_edges().map(|(a, b, e)| (a, e, b)).collect();
+
+    let nodes = |a: &HashSet<&TagGraphNode>, b: &HashSet<&TagGraphNode>| {
+        let mut nodes: Vec<TagGraphNode> = a.difference(b).map(|&n| n.clone()).collect();
+        nodes.sort();
+        nodes
+    };
+    let edges = |a: &HashSet<(&TagGraphNode, &Relation, &TagGraphNode)>,
+                 b: &HashSet<(&TagGraphNode, &Relation, &TagGraphNode)>| {
+        let mut edges: Vec<(TagGraphNode, Relation, TagGraphNode)> = a
+            .difference(b)
+            .map(|&(x, e, y)| (x.clone(), e.clone(), y.clone()))
+            .collect();
+        edges.sort();
+        edges
+    };
+
+    GraphDiff {
+        added_nodes: nodes(&new_nodes, &old_nodes),
+        removed_nodes: nodes(&old_nodes, &new_nodes),
+        added_edges: edges(&new_edges, &old_edges),
+        removed_edges: edges(&old_edges, &new_edges),
+    }
+}
@@ -0,0 +1,708 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::{trace, warn};
+use petgraph::{graph::NodeIndex, visit::EdgeRef, Directed, Direction};
+use walkdir::WalkDir;
+
+use crate::{
+    add_tag_node, canonicalize, canonicalize_dir, inherit_tags, is_valid_tag, path_node,
+    read_tagfile_contents, read_tagfile_with_includes, scan, strip_bom,
+    tagfile::{write_contents_atomic, TagfileDocument},
+    try_parse_tagfile_line, Error, HashSetGraph, Relation, ScanOptions, TagGraphNode, TagOperation,
+    TagfileOptions, GLOB_PREFIX,
+};
+
+/// Gets the tag file holding a path's tags: `dir.tags` inside a directory, or a sidecar next to a file.
+/// An existing `<name>.tags` sidecar is preferred, otherwise it's `<stem>.tags`. The extension is the one in
+/// `options`.
+pub fn sidecar_tagfile(path: &Path, options: &ScanOptions) -> Result<PathBuf, Error> {
+    let candidates = sidecar_candidates(path, options)?;
+    Ok(candidates
+        .iter()
+        .find(|c| c.exists())
+        .unwrap_or(&candidates[candidates.len() - 1])
+        .clone())
+}
+
+/// Gets every tag file that could hold a path's tags, most specific first
+fn sidecar_candidates(path: &Path, options: &ScanOptions) -> Result<Vec<PathBuf>, Error> {
+    if options.is_tagfile(path) {
+        return Err(Error::CannotTagTagfile {
+            path: path.to_path_buf(),
+        });
+    }
+    if path.is_dir() {
+        return Ok(vec![path.join(options.dir_tagfile())]);
+    }
+
+    let (Some(name), Some(stem)) = (path.file_name(), path.file_stem()) else {
+        return Err(Error::InvalidPath {
+            path: path.to_path_buf(),
+        });
+    };
+    let extension = format!(".{}", options.tagfile_extension);
+    let mut by_name = name.to_os_string();
+    by_name.push(&extension);
+    let mut by_stem = stem.to_os_string();
+    by_stem.push(&extension);
+    if by_name == by_stem {
+        Ok(vec![path.with_file_name(by_name)])
+    } else {
+        Ok(vec![
+            path.with_file_name(by_name),
+            path.with_file_name(by_stem),
+        ])
+    }
+}
+
+/// Adds a tag to a file or directory under `root` by appending it to its tag file, which is created if needed.
+/// Does nothing if the tag file already has the tag. A `-tag` or `!tag` line excluding it is removed, since
+/// it would otherwise win over the added tag. Pass the graph built from `root` to update it as well,
+/// which saves rescanning, along with the options it was scanned with.
+pub fn add_tag(
+    root: &Path,
+    options: &ScanOptions,
+    file: &Path,
+    tag: &str,
+    tag_graph: Option<&mut HashSetGraph<TagGraphNode, Relation, Directed>>,
+) -> Result<(), Error> {
+    let root = canonicalize_dir(root)?;
+    let file = canonicalize(file)?;
+    if !file.starts_with(&root) {
+        return Err(Error::NotUnderRoot { path: file, root });
+    }
+    let tagfile = sidecar_tagfile(&file, options)?;
+    let line = tag_line(tag)?;
+
+    // A `-tag` or `!tag` line would still win over the added one, so it goes
+    let excluded = TagOperation::Remove(tag.to_string());
+    for other in sidecar_candidates(&file, options)? {
+        if other == tagfile || !other.exists() {
+            continue;
+        }
+        let mut document = TagfileDocument::open(&other)?;
+        if document.remove_operation(&excluded) {
+            trace!("Removing exclusion of {} from {:?}", tag, other);
+            document.save(&other, false)?;
+        }
+    }
+    let mut document = TagfileDocument::open(&tagfile)?;
+    let mut changed = document.remove_operation(&excluded);
+    if !document.contains(&TagOperation::Add(tag.to_string())) {
+        trace!("Adding tag {} to {:?}", tag, tagfile);
+        document.push_line(&line);
+        changed = true;
+    }
+    if changed {
+        document.save(&tagfile, false)?;
+    }
+
+    if let Some(tag_graph) = tag_graph {
+        let target = match path_node(tag_graph, &file) {
+            Some(target) => target,
+            None => add_path_node(tag_graph, &file),
+        };
+        let tag_root = tag_graph.get_node(&TagGraphNode::RootTag);
+        if let Some(t) = add_tag_node(tag_graph, tag_root, tag) {
+            while let Some(e) = tag_graph
+                .graph
+                .edges_connecting(target, t)
+                .find(|e| *e.weight() == Relation::ExcludesTag)
+                .map(|e| e.id())
+            {
+                tag_graph.graph.remove_edge(e);
+            }
+            tag_graph.graph.update_edge(target, t, Relation::HasTag);
+            tag_graph
+                .graph
+                .update_edge(t, target, Relation::TagAssignedTo);
+        }
+        if file.is_dir() {
+            inherit_tags(tag_graph);
+        }
+    }
+    Ok(())
+}
+
+/// Removes a tag from a file or directory by deleting it from its tag files.
+/// A tag file left with nothing in it is deleted, unless `keep_empty` is set.
+/// Returns whether anything changed. Fails with `Error::TagIsInherited` if the tag only comes from
+/// the `dir.tags` of a directory above the path.
+pub fn remove_tag(
+    file: &Path,
+    tag: &str,
+    keep_empty: bool,
+    options: &ScanOptions,
+) -> Result<bool, Error> {
+    let file = canonicalize(file)?;
+    let removed = TagOperation::Add(tag.to_string());
+
+    let mut changed = false;
+    for tagfile in sidecar_candidates(&file, options)? {
+        if !tagfile.exists() {
+            continue;
+        }
+        let mut document = TagfileDocument::open(&tagfile)?;
+        if !document.remove_operation(&removed) {
+            continue;
+        }
+
+        changed = true;
+        trace!("Removing tag {} from {:?}", tag, tagfile);
+        if document.is_blank() && !keep_empty {
+            fs::remove_file(&tagfile)?;
+        } else {
+            document.save(&tagfile, false)?;
+        }
+    }
+
+    if !changed {
+        // Tags on a directory apply to everything inside it
+        let mut ancestors = file.ancestors();
+        ancestors.next();
+        for dir in ancestors {
+            let tagfile = dir.join(options.dir_tagfile());
+            if tagfile.exists()
+                && read_tagfile_with_includes(&tagfile)?
+                    .contains(&TagOperation::Add(tag.to_string()))
+            {
+                return Err(Error::TagIsInherited {
+                    path: file,
+                    tag: tag.to_string(),
+                    tagfile,
+                });
+            }
+        }
+    }
+    Ok(changed)
+}
+
+/// Sets the tags a tag file assigns, replacing the ones it had. Comments, exclusions and the lines of tags
+/// that are kept stay where they were, and new tags are appended. The file is written atomically, and is
+/// created if needed.
+pub fn write_tagfile(path: &Path, tags: &[String]) -> Result<(), Error> {
+    let lines = tags
+        .iter()
+        .map(|tag| tag_line(tag))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut document = TagfileDocument::open(path)?;
+    let mut changed = document.edit_operations(|operation| match operation {
+        TagOperation::Add(tag) if !tags.contains(tag) => Some(None),
+        _ => None,
+    });
+    for (tag, line) in tags.iter().zip(lines) {
+        if !document.contains(&TagOperation::Add(tag.clone())) {
+            document.push_line(&line);
+            changed = true;
+        }
+    }
+
+    if changed || !path.exists() {
+        trace!("Writing tags {:?} to {:?}", tags, path);
+        document.save(path, false)?;
+    }
+    Ok(())
+}
+
+/// Updates the tag files under `root` to match the tags in a graph built from it.
+/// Where a path's own tags or exclusions differ from what's on disk, the lines that differ are added to or
+/// removed from its tag files, like `add_tag` and `remove_tag` do. Tag files left empty are deleted.
+/// Tags that come from somewhere other than a path's own tag files, like a `_patterns.tags`, can't be
+/// removed this way and are skipped with a warning. `options` should be the ones the graph was scanned with.
+pub fn sync_tags_to_disk(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    root: &Path,
+    options: &ScanOptions,
+) -> Result<(), Error> {
+    let root = canonicalize_dir(root)?;
+    let (on_disk, _) = scan(&root, options)?;
+
+    for node in tag_graph.graph.node_indices() {
+        let path = match &tag_graph.graph[node] {
+            TagGraphNode::File { path }
+            | TagGraphNode::Directory { path }
+            | TagGraphNode::Symlink { path, .. } => path,
+            _ => continue,
+        };
+        if !path.starts_with(&root) || fs::symlink_metadata(path).is_err() {
+            continue;
+        }
+        let wanted = own_operations(tag_graph, Some(node));
+        let current = own_operations(&on_disk, path_node(&on_disk, path));
+        if wanted == current {
+            continue;
+        }
+
+        let removed: Vec<&TagOperation> = current.iter().filter(|o| !wanted.contains(o)).collect();
+        let added: Vec<&TagOperation> = wanted.iter().filter(|o| !current.contains(o)).collect();
+        let target = sidecar_tagfile(path, options)?;
+        let mut not_removed = removed.clone();
+        for tagfile in sidecar_candidates(path, options)? {
+            if !tagfile.exists() {
+                continue;
+            }
+            let mut document = TagfileDocument::open(&tagfile)?;
+            let mut changed = false;
+            for operation in &removed {
+                if document.remove_operation(operation) {
+                    not_removed.retain(|o| o != operation);
+                    changed = true;
+                }
+            }
+            if !changed {
+                continue;
+            }
+            trace!("Removing {:?} from {:?}", removed, tagfile);
+            // Empty tag files are deleted, unless the tags being added are about to go into them
+            if document.is_blank() && (added.is_empty() || tagfile != target) {
+                fs::remove_file(&tagfile)?;
+            } else {
+                document.save(&tagfile, false)?;
+            }
+        }
+        for operation in not_removed {
+            warn!(
+                "Can't remove {:?} from {:?}, since it doesn't come from the path's own tag files",
+                operation, path
+            );
+        }
+
+        if !added.is_empty() {
+            let mut document = TagfileDocument::open(&target)?;
+            for operation in &added {
+                document.push_line(&operation_line(operation)?);
+            }
+            trace!("Adding {:?} to {:?}", added, target);
+            document.save(&target, false)?;
+        }
+    }
+    Ok(())
+}
+
+/// Gets the tags assigned to or excluded from a node by its own tag files, sorted by tag
+fn own_operations(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    node: Option<NodeIndex>,
+) -> Vec<TagOperation> {
+    let Some(node) = node else {
+        return vec![];
+    };
+    let mut operations: Vec<TagOperation> = tag_graph
+        .graph
+        .edges(node)
+        .filter_map(|e| match (e.weight(), &tag_graph.graph[e.target()]) {
+            (Relation::HasTag, TagGraphNode::Tag(tag)) => Some(TagOperation::Add(tag.clone())),
+            (Relation::ExcludesTag, TagGraphNode::Tag(tag)) => {
+                Some(TagOperation::Remove(tag.clone()))
+            }
+            _ => None,
+        })
+        .collect();
+    operations.sort_by(|a, b| operation_tag(a).cmp(&operation_tag(b)));
+    operations
+}
+
+/// The tag files changed (or, for a dry run, that would be changed) by `rename_tag`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenameReport {
+    pub files: Vec<PathBuf>,
+    /// Tag files that have the tag but couldn't be rewritten, like JSON sidecars where it comes from an
+    /// attribute. Their tags in the graph still have the old name after a rescan.
+    pub skipped: Vec<PathBuf>,
+}
+
+impl RenameReport {
+    pub fn files_touched(&self) -> usize {
+        self.files.len()
+    }
+}
+
+/// Renames a tag in every tag file under `root`, including lines removing it.
+/// Where a tag file already has the new tag, the old line is dropped rather than duplicated.
+/// With `dry_run` set, nothing is written and the report lists the files that would change. Renaming a tag to
+/// itself changes nothing. Tag files are found with the extension in `options`.
+pub fn rename_tag(
+    root: &Path,
+    options: &ScanOptions,
+    old: &str,
+    new: &str,
+    dry_run: bool,
+) -> Result<RenameReport, Error> {
+    tag_line(new)?;
+    if old == new {
+        return Ok(RenameReport::default());
+    }
+    rewrite_tags(root, options, dry_run, |tag| {
+        (tag == old).then(|| new.to_string())
+    })
+}
+
+/// Rewrites the tags `rename` gives a new name for in every tag file under `root`, whatever its format.
+/// A renamed tag is dropped if the same line (or JSON sidecar) already has the new one, so nothing is duplicated.
+fn rewrite_tags(
+    root: &Path,
+    options: &ScanOptions,
+    dry_run: bool,
+    rename: impl Fn(&str) -> Option<String>,
+) -> Result<RenameReport, Error> {
+    let mut report = RenameReport::default();
+
+    for tagfile in find_tagfiles(root, options) {
+        let name = tagfile.file_name().unwrap_or_default();
+        let rewritten = if *name == *options.patterns_tagfile() {
+            rewrite_tag_lists(&tagfile, ':', &rename)?
+        } else if *name == *options.manifest_tagfile() {
+            rewrite_tag_lists(&tagfile, '\t', &rename)?
+        } else if options.is_json_tagfile(&tagfile) {
+            rewrite_json_tagfile(&tagfile, &rename, &mut report)?
+        } else {
+            rewrite_tagfile_lines(&tagfile, &rename)?
+        };
+        let Some(contents) = rewritten else {
+            continue;
+        };
+
+        trace!("Renaming tags in {:?}", tagfile);
+        if !dry_run {
+            write_contents_atomic(&tagfile, contents.as_bytes(), false)?;
+        }
+        report.files.push(tagfile);
+    }
+
+    report.files.sort();
+    report.skipped.sort();
+    Ok(report)
+}
+
+/// Renames the tags in a tag file with one tag per line. Returns the new contents, if anything changed.
+fn rewrite_tagfile_lines(
+    tagfile: &Path,
+    rename: &impl Fn(&str) -> Option<String>,
+) -> Result<Option<String>, Error> {
+    let mut document = TagfileDocument::open(tagfile)?;
+    // The operations in the file, so each renamed one is only written once
+    let mut present: Vec<TagOperation> = document.operations().cloned().collect();
+    let mut result = Ok(());
+    let changed = document.edit_operations(|operation| {
+        let renamed = rename_operation(operation, rename)?;
+        if present.contains(&renamed) {
+            return Some(None);
+        }
+        let line = operation_line(&renamed);
+        present.push(renamed);
+        match line {
+            Ok(line) => Some(Some(line)),
+            Err(e) => {
+                result = Err(e);
+                None
+            }
+        }
+    });
+    result?;
+    Ok(changed.then(|| document.to_string()))
+}
+
+/// Renames the tags in a patterns tag file or a manifest, where each line has something to tag, `separator`,
+/// and a comma separated list of tags. Everything else on a line is kept as it was. Returns the new
+/// contents, if anything changed.
+fn rewrite_tag_lists(
+    tagfile: &Path,
+    separator: char,
+    rename: &impl Fn(&str) -> Option<String>,
+) -> Result<Option<String>, Error> {
+    let contents = read_tagfile_contents(tagfile)?;
+    let body = strip_bom(&contents);
+    let mut rewritten = contents[..contents.len() - body.len()].to_string();
+    let mut changed = false;
+    for line in body.split_inclusive('\n') {
+        let text = line.trim_end_matches(['\r', '\n']);
+        let (head, tags) = match text.split_once(separator) {
+            Some(split) if !text.trim_start().starts_with('#') => split,
+            _ => {
+                rewritten.push_str(line);
+                continue;
+            }
+        };
+
+        let segments: Vec<&str> = tags.split(',').collect();
+        let mut present: Vec<TagOperation> = segments
+            .iter()
+            .filter_map(|segment| list_operation(segment))
+            .collect();
+        let mut kept = vec![];
+        for segment in segments {
+            let Some(renamed) =
+                list_operation(segment).and_then(|operation| rename_operation(&operation, rename))
+            else {
+                kept.push(segment.to_string());
+                continue;
+            };
+            changed = true;
+            if present.contains(&renamed) {
+                continue;
+            }
+            // Keep the spacing around the tag
+            let trimmed = segment.trim();
+            let start = segment.len() - segment.trim_start().len();
+            kept.push(format!(
+                "{}{}{}",
+                &segment[..start],
+                operation_line(&renamed)?,
+                &segment[start + trimmed.len()..]
+            ));
+            present.push(renamed);
+        }
+
+        rewritten.push_str(head);
+        rewritten.push(separator);
+        rewritten.push_str(&kept.join(","));
+        rewritten.push_str(&line[text.len()..]);
+    }
+    Ok(changed.then_some(rewritten))
+}
+
+/// Parses one tag in the comma separated list of a patterns tag file or manifest
+fn list_operation(segment: &str) -> Option<TagOperation> {
+    try_parse_tagfile_line(segment, &TagfileOptions::default())
+        .ok()
+        .flatten()
+}
+
+/// Renames the tags in a JSON sidecar's `tags`. It's written back formatted by serde_json. Tags that come
+/// from its `attrs` can't be renamed, so the file is added to the report's `skipped` instead. Returns the
+/// new contents, if anything changed.
+#[cfg(feature = "serde")]
+fn rewrite_json_tagfile(
+    tagfile: &Path,
+    rename: &impl Fn(&str) -> Option<String>,
+    report: &mut RenameReport,
+) -> Result<Option<String>, Error> {
+    use serde_json::Value;
+
+    let contents = read_tagfile_contents(tagfile)?;
+    let Ok(mut json) = serde_json::from_str::<Value>(strip_bom(&contents)) else {
+        warn!("Skipping {:?}, which isn't valid JSON", tagfile);
+        return Ok(None);
+    };
+
+    let from_attrs = json
+        .get("attrs")
+        .and_then(Value::as_object)
+        .is_some_and(|attrs| {
+            attrs.iter().any(|(name, value)| {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    Value::Number(_) | Value::Bool(_) => value.to_string(),
+                    _ => return false,
+                };
+                rename(&format!("{}:{}", name, value)).is_some()
+            })
+        });
+    if from_attrs {
+        warn!(
+            "Can't rename a tag that comes from an attribute in {:?}",
+            tagfile
+        );
+        report.skipped.push(tagfile.to_path_buf());
+    }
+
+    let Some(tags) = json.get_mut("tags").and_then(Value::as_array_mut) else {
+        return Ok(None);
+    };
+    let mut changed = false;
+    let mut renamed_tags: Vec<Value> = vec![];
+    for tag in tags.drain(..) {
+        let tag = match tag.as_str().and_then(|t| rename(t.trim())) {
+            Some(renamed) => {
+                changed = true;
+                Value::String(renamed)
+            }
+            None => tag,
+        };
+        if !renamed_tags.contains(&tag) {
+            renamed_tags.push(tag);
+        }
+    }
+    *tags = renamed_tags;
+    if !changed {
+        return Ok(None);
+    }
+    Ok(Some(format!("{}\n", serde_json::to_string_pretty(&json)?)))
+}
+
+/// JSON sidecars aren't read without the `serde` feature, so there's nothing to rename in them
+#[cfg(not(feature = "serde"))]
+fn rewrite_json_tagfile(
+    _tagfile: &Path,
+    _rename: &impl Fn(&str) -> Option<String>,
+    _report: &mut RenameReport,
+) -> Result<Option<String>, Error> {
+    Ok(None)
+}
+
+/// Gets an operation with its tag renamed, if `rename` renames it
+fn rename_operation(
+    operation: &TagOperation,
+    rename: &impl Fn(&str) -> Option<String>,
+) -> Option<TagOperation> {
+    match operation {
+        TagOperation::Add(tag) => Some(TagOperation::Add(rename(tag)?)),
+        TagOperation::Remove(tag) => Some(TagOperation::Remove(rename(tag)?)),
+        TagOperation::Glob(_) => None,
+    }
+}
+
+/// Merges the tag `from` into `into`. Tag files under `root` are rewritten like `rename_tag` does, so files
+/// that had both end up with `into` once. Tags below `from` in the hierarchy are moved below `into`, so
+/// `from/child` becomes `into/child`. If a graph is passed, everything the merged tags were assigned to (or
+/// excluded from) is moved onto their new tags, and their nodes are removed. Merging a tag into itself changes
+/// nothing.
+pub fn merge_tags(
+    root: &Path,
+    options: &ScanOptions,
+    from: &str,
+    into: &str,
+    tag_graph: Option<&mut HashSetGraph<TagGraphNode, Relation, Directed>>,
+) -> Result<RenameReport, Error> {
+    tag_line(into)?;
+    if from == into {
+        return Ok(RenameReport::default());
+    }
+    let rename = |tag: &str| {
+        if tag == from {
+            return Some(into.to_string());
+        }
+        let child = tag.strip_prefix(from)?.strip_prefix('/')?;
+        Some(format!("{}/{}", into, child))
+    };
+    let report = rewrite_tags(root, options, false, rename)?;
+
+    if let Some(tag_graph) = tag_graph {
+        let merged: Vec<(String, String)> = tag_graph
+            .iter_nodes()
+            .filter_map(|node| match node {
+                TagGraphNode::Tag(tag) => Some((tag.clone(), rename(tag)?)),
+                _ => None,
+            })
+            .collect();
+        if merged.is_empty() {
+            return Ok(report);
+        }
+        let tag_root = tag_graph.get_node(&TagGraphNode::RootTag);
+        for (old, new) in merged {
+            let old_tag = TagGraphNode::Tag(old);
+            let Some(&old_node) = tag_graph.map.get(&old_tag) else {
+                continue;
+            };
+            if let Some(new_node) = add_tag_node(tag_graph, tag_root, &new) {
+                retarget_tag(tag_graph, tag_root, old_node, new_node);
+            }
+            tag_graph.remove_node(&old_tag);
+        }
+        inherit_tags(tag_graph);
+    }
+    Ok(report)
+}
+
+/// Moves the assignments and exclusions of one tag node onto another
+fn retarget_tag(
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+    tag_root: NodeIndex,
+    from_node: NodeIndex,
+    into_node: NodeIndex,
+) {
+    let retargeted: Vec<(NodeIndex, Relation)> = tag_graph
+        .graph
+        .edges_directed(from_node, Direction::Incoming)
+        .filter(|e| e.source() != tag_root)
+        .filter(|e| matches!(e.weight(), Relation::HasTag | Relation::ExcludesTag))
+        .map(|e| (e.source(), e.weight().clone()))
+        .collect();
+    for (node, relation) in retargeted {
+        // Nodes that already had `into` keep it as it was
+        let has_into = tag_graph.graph.edges(node).any(|e| {
+            e.target() == into_node
+                && matches!(e.weight(), Relation::HasTag | Relation::ExcludesTag)
+        });
+        if has_into {
+            continue;
+        }
+        if relation == Relation::HasTag {
+            tag_graph
+                .graph
+                .update_edge(into_node, node, Relation::TagAssignedTo);
+        }
+        tag_graph.graph.update_edge(node, into_node, relation);
+    }
+}
+
+/// Finds every tag file under a directory, in each of the formats the scanner reads
+pub(crate) fn find_tagfiles(root: &Path, options: &ScanOptions) -> Vec<PathBuf> {
+    WalkDir::new(root)
+        .into_iter()
+        .flatten()
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .filter(|path| options.is_tagfile(path) && !options.is_write_leftover(path))
+        .filter(|path| cfg!(feature = "serde") || !options.is_json_tagfile(path))
+        .collect()
+}
+
+fn operation_tag(operation: &TagOperation) -> Option<&str> {
+    match operation {
+        TagOperation::Add(tag) | TagOperation::Remove(tag) => Some(tag),
+        TagOperation::Glob(_) => None,
+    }
+}
+
+fn operation_line(operation: &TagOperation) -> Result<String, Error> {
+    match operation {
+        TagOperation::Add(tag) => tag_line(tag),
+        TagOperation::Remove(tag) => Ok(format!("-{}", tag_line(tag)?.trim_start_matches('\\'))),
+        TagOperation::Glob(pattern) => Ok(format!("{}{}", GLOB_PREFIX, pattern)),
+    }
+}
+
+/// Formats a tag as a tag file line, escaping a leading `-` or `!` so it isn't read as a removal,
+/// a leading `#` so it isn't read as a comment, and a leading `@` or `glob:` so it isn't read as a directive.
+/// Tags the parser would reject, like `/` or ones with control characters, are rejected here too.
+pub(crate) fn tag_line(tag: &str) -> Result<String, Error> {
+    let trimmed = tag.trim();
+    if trimmed.is_empty() || trimmed != tag || !is_valid_tag(tag) {
+        return Err(Error::InvalidTag {
+            tag: tag.to_string(),
+        });
+    }
+    if tag.starts_with(['-', '!', '#', '@']) || tag.starts_with(GLOB_PREFIX) {
+        Ok(format!("\\{}", tag))
+    } else {
+        Ok(tag.to_string())
+    }
+}
+
+/// Adds a node for a path that wasn't in the graph yet, attached to its parent directory
+pub(crate) fn add_path_node(
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+    path: &Path,
+) -> petgraph::graph::NodeIndex {
+    let node = if path.is_dir() {
+        tag_graph.get_node_move(TagGraphNode::Directory {
+            path: path.to_path_buf(),
+        })
+    } else {
+        tag_graph.get_node_move(TagGraphNode::File {
+            path: path.to_path_buf(),
+        })
+    };
+    if let Some(parent) = path.parent() {
+        let parent = tag_graph.get_node_move(TagGraphNode::Directory {
+            path: parent.to_path_buf(),
+        });
+        tag_graph.graph.update_edge(parent, node, Relation::Child);
+        tag_graph.graph.update_edge(node, parent, Relation::Parent);
+    }
+    node
+}
@@ -0,0 +1,138 @@
+use std::{fs, io::Cursor, path::Path};
+
+use ::exif::{Error as ExifError, In, Reader, Tag, Value};
+use log::{trace, warn};
+use petgraph::Directed;
+use walkdir::WalkDir;
+
+use crate::{
+    apply_tag_operations, canonicalize_dir, path_node, Error, HashSetGraph, Relation, TagGraphNode,
+    TagOperation,
+};
+
+/// The EXIF fields read as tags, with the namespace each one's tag goes in
+const FIELDS: [(Tag, &str); 3] = [
+    (Tag::ImageDescription, "description"),
+    (Tag::Artist, "artist"),
+    (Tag::Copyright, "copyright"),
+];
+
+/// Tags the JPEG and TIFF files under `root` with their EXIF metadata. `ImageDescription`, `Artist` and
+/// `Copyright` become `description:`, `artist:` and `copyright:` tags, and the XMP `dc:subject` keywords
+/// become tags as they are. Slashes aren't escaped, so they make hierarchical tags, like in tag files.
+/// Files that aren't in the graph yet are added without a parent, so pass the graph built from `root`.
+/// Metadata that can't be read is skipped with a warning.
+pub fn add_exif_tags_to_graph(
+    root: impl AsRef<Path>,
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+) -> Result<(), Error> {
+    let root = canonicalize_dir(root.as_ref())?;
+    let tag_root = tag_graph.get_node(&TagGraphNode::RootTag);
+    for entry in WalkDir::new(&root)
+        .sort_by_file_name()
+        .into_iter()
+        .flatten()
+    {
+        let path = entry.path();
+        let is_image = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                ["jpg", "jpeg", "tif", "tiff"]
+                    .iter()
+                    .any(|image| ext.eq_ignore_ascii_case(image))
+            });
+        if !entry.file_type().is_file() || !is_image {
+            continue;
+        }
+        let contents = fs::read(path)?;
+
+        let mut tags = vec![];
+        match Reader::new().read_from_container(&mut Cursor::new(&contents)) {
+            Ok(exif) => {
+                for (tag, namespace) in FIELDS {
+                    if let Some(Value::Ascii(values)) =
+                        exif.get_field(tag, In::PRIMARY).map(|f| &f.value)
+                    {
+                        tags.extend(
+                            values
+                                .iter()
+                                .filter_map(|value| exif_text(value))
+                                .map(|value| format!("{}:{}", namespace, value)),
+                        );
+                    }
+                }
+            }
+            Err(ExifError::NotFound(_)) => {}
+            Err(e) => warn!("Skipping the EXIF metadata of {:?}: {}", path, e),
+        }
+        tags.extend(xmp_subjects(&contents));
+        if tags.is_empty() {
+            continue;
+        }
+
+        trace!("EXIF metadata of {:?} has {:?}", path, tags);
+        let operations: Vec<TagOperation> = tags.into_iter().map(TagOperation::Add).collect();
+        let target = match path_node(tag_graph, path) {
+            Some(node) => node,
+            None => tag_graph.get_node_move(TagGraphNode::File {
+                path: path.to_path_buf(),
+            }),
+        };
+        apply_tag_operations(tag_graph, tag_root, path, &operations, &[target]);
+    }
+    Ok(())
+}
+
+/// Reads an EXIF string, which is meant to be ASCII but is often UTF-8, with its whitespace collapsed
+fn exif_text(value: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(value);
+    let text = text
+        .trim_end_matches('\0')
+        .split_whitespace()
+        .collect::<Vec<_>>();
+    (!text.is_empty()).then(|| text.join(" "))
+}
+
+/// Gets the `rdf:li` keywords in the `dc:subject` of an XMP packet embedded in a file
+fn xmp_subjects(contents: &[u8]) -> Vec<String> {
+    let Some(start) = find(contents, b"<dc:subject") else {
+        return vec![];
+    };
+    let Some(end) = find(&contents[start..], b"</dc:subject>") else {
+        return vec![];
+    };
+    let subject = String::from_utf8_lossy(&contents[start..start + end]);
+
+    let mut keywords = vec![];
+    let mut rest = subject.as_ref();
+    while let Some(open) = rest.find("<rdf:li") {
+        let Some(close) = rest[open..].find('>') else {
+            break;
+        };
+        rest = &rest[open + close + 1..];
+        let Some(end) = rest.find("</rdf:li>") else {
+            break;
+        };
+        let keyword = unescape_xml(rest[..end].trim());
+        if !keyword.is_empty() {
+            keywords.push(keyword);
+        }
+        rest = &rest[end..];
+    }
+    keywords
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
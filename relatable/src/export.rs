@@ -0,0 +1,447 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Write,
+    io,
+    path::Path,
+};
+
+use petgraph::{
+    graph::NodeIndex,
+    visit::{EdgeRef, IntoEdgeReferences},
+    Directed, Direction,
+};
+
+use crate::{
+    effective_tags_with_sources, node_kind, node_label, Error, HashSetGraph, Relation, TagGraphNode,
+};
+
+/// Options for `to_dot`
+#[derive(Debug, Default, Clone)]
+pub struct DotOptions {
+    /// Leaves out the edges that mirror another one, like `Parent` for `Child` and `TagAssignedTo` for
+    /// `HasTag`, which roughly halves the edges to lay out
+    pub omit_reverse_edges: bool,
+    /// Leaves out the relation labels on edges, leaving just their styles
+    pub hide_edge_labels: bool,
+}
+
+/// Writes a graph in the Graphviz DOT language, for rendering with `dot -Tsvg`. Each kind of node gets its own
+/// shape and color, and each relation its own edge style. Nodes are labeled with `node_label`, and files and
+/// directories have their full path as a tooltip.
+pub fn to_dot(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    options: &DotOptions,
+) -> String {
+    let mut dot = String::from("digraph {\n");
+    dot.push_str("    node [fontname=\"Helvetica\"];\n");
+    dot.push_str("    edge [fontname=\"Helvetica\", fontsize=10];\n");
+    for idx in tag_graph.graph.node_indices() {
+        let node = &tag_graph.graph[idx];
+        let _ = write!(
+            dot,
+            "    n{} [label={}, {}",
+            idx.index(),
+            dot_string(&node_label(node)),
+            node_style(node)
+        );
+        if let Some(path) = node.path() {
+            let _ = write!(dot, ", tooltip={}", dot_string(&path.to_string_lossy()));
+        }
+        dot.push_str("];\n");
+    }
+    for edge in tag_graph.graph.edge_references() {
+        if options.omit_reverse_edges && is_reverse(edge.weight()) {
+            continue;
+        }
+        let _ = write!(
+            dot,
+            "    n{} -> n{} [{}",
+            edge.source().index(),
+            edge.target().index(),
+            edge_style(edge.weight())
+        );
+        if !options.hide_edge_labels {
+            let _ = write!(
+                dot,
+                ", label={}",
+                dot_string(&format!("{:?}", edge.weight()))
+            );
+        }
+        dot.push_str("];\n");
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Quotes a string for DOT
+fn dot_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            '\r' => {}
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Writes the neighborhood of a node as a Mermaid flowchart, e.g. for a Markdown note. Nodes up to `depth`
+/// edges away from `focus`, following edges either way, are included nearest first, and at most `max_nodes` of
+/// them. Edges are labeled with their relation, and like `DotOptions::omit_reverse_edges`, only one edge of each
+/// mirrored pair is drawn.
+pub fn to_mermaid(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    focus: NodeIndex,
+    depth: usize,
+    max_nodes: usize,
+) -> String {
+    let mut mermaid = String::from("graph TD\n");
+    if !tag_graph.graph.contains_node(focus) || max_nodes == 0 {
+        return mermaid;
+    }
+
+    let mut included = vec![focus];
+    let mut seen = HashSet::from([focus]);
+    let mut to_visit = VecDeque::from([(focus, 0)]);
+    let mut left_out = 0;
+    while let Some((node, distance)) = to_visit.pop_front() {
+        if distance == depth {
+            continue;
+        }
+        let neighbors = tag_graph
+            .graph
+            .neighbors_directed(node, Direction::Outgoing)
+            .chain(
+                tag_graph
+                    .graph
+                    .neighbors_directed(node, Direction::Incoming),
+            );
+        for neighbor in neighbors {
+            if !seen.insert(neighbor) {
+                continue;
+            }
+            if included.len() < max_nodes {
+                included.push(neighbor);
+                to_visit.push_back((neighbor, distance + 1));
+            } else {
+                left_out += 1;
+            }
+        }
+    }
+
+    for &node in &included {
+        let _ = writeln!(
+            mermaid,
+            "    n{}[\"{}\"]",
+            node.index(),
+            mermaid_escape(&node_label(&tag_graph.graph[node]))
+        );
+    }
+    let included: HashSet<NodeIndex> = included.into_iter().collect();
+    for edge in tag_graph.graph.edge_references() {
+        if is_reverse(edge.weight())
+            || !included.contains(&edge.source())
+            || !included.contains(&edge.target())
+        {
+            continue;
+        }
+        let _ = writeln!(
+            mermaid,
+            "    n{} -->|{:?}| n{}",
+            edge.source().index(),
+            edge.weight(),
+            edge.target().index()
+        );
+    }
+    if left_out > 0 {
+        let _ = writeln!(mermaid, "    %% {} more nodes left out", left_out);
+    }
+    mermaid
+}
+
+/// Replaces the characters Mermaid would read as syntax with entity codes, like `#91;` for `[`
+fn mermaid_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' | '#' | '[' | ']' | '{' | '}' | '(' | ')' | '<' | '>' | '/' | '\\' | '|' | '`' => {
+                let _ = write!(escaped, "#{};", c as u32);
+            }
+            '\n' | '\r' => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes which tags each file has as CSV, with a `path,tag,source` header. `source` is `file` for tags
+/// assigned to the path itself, and `directory` for ones inherited from a directory above it. Rows are
+/// sorted by path and then tag, and written as they're worked out, so wrap `writer` in a `BufWriter` for
+/// big graphs. With `include_directories` set, directories get rows too.
+pub fn to_csv(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    writer: impl io::Write,
+    include_directories: bool,
+) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(["path", "tag", "source"])?;
+    let mut nodes: Vec<(&Path, NodeIndex)> = tag_graph
+        .graph
+        .node_indices()
+        .filter_map(|idx| match &tag_graph.graph[idx] {
+            TagGraphNode::File { path } => Some((path.as_path(), idx)),
+            TagGraphNode::Directory { path } if include_directories => Some((path.as_path(), idx)),
+            _ => None,
+        })
+        .collect();
+    nodes.sort();
+
+    for (path, node) in nodes {
+        let path = path.to_string_lossy();
+        for (tag, source) in effective_tags_with_sources(tag_graph, node) {
+            let source = if source == node { "file" } else { "directory" };
+            writer.write_record([path.as_ref(), tag.as_str(), source])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes a graph as GraphML, e.g. for Gephi or yEd. Nodes have `kind`, `label` and `path` attributes, and
+/// edges a `relation` attribute. Node ids come from the node's weight, like `file:%2Fphotos%2Fbeach.jpg`, and
+/// everything is sorted by id, so exporting the same graph twice gives the same file.
+pub fn to_graphml(tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>) -> String {
+    let (nodes, edges) = sorted_by_id(tag_graph);
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(
+        "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\" \
+         xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+         xsi:schemaLocation=\"http://graphml.graphdrawing.org/xmlns \
+         http://graphml.graphdrawing.org/xmlns/1.0/graphml.xsd\">\n",
+    );
+    for (key, domain) in [
+        ("kind", "node"),
+        ("label", "node"),
+        ("path", "node"),
+        ("relation", "edge"),
+    ] {
+        let _ = writeln!(
+            xml,
+            "  <key id=\"{key}\" for=\"{domain}\" attr.name=\"{key}\" attr.type=\"string\"/>"
+        );
+    }
+    xml.push_str("  <graph id=\"G\" edgedefault=\"directed\">\n");
+    for (id, node) in &nodes {
+        let _ = writeln!(xml, "    <node id=\"{}\">", xml_escape(id));
+        let _ = writeln!(xml, "      <data key=\"kind\">{}</data>", node_kind(node));
+        let _ = writeln!(
+            xml,
+            "      <data key=\"label\">{}</data>",
+            xml_escape(&node_label(node))
+        );
+        if let Some(path) = node.path() {
+            let _ = writeln!(
+                xml,
+                "      <data key=\"path\">{}</data>",
+                xml_escape(&path.to_string_lossy())
+            );
+        }
+        xml.push_str("    </node>\n");
+    }
+    for (source, target, relation) in &edges {
+        let _ = writeln!(
+            xml,
+            "    <edge source=\"{}\" target=\"{}\">",
+            xml_escape(source),
+            xml_escape(target)
+        );
+        let _ = writeln!(xml, "      <data key=\"relation\">{:?}</data>", relation);
+        xml.push_str("    </edge>\n");
+    }
+    xml.push_str("  </graph>\n</graphml>\n");
+    xml
+}
+
+/// Writes a graph as GEXF 1.3, the native format of Gephi, with the same attributes and ids as `to_graphml`
+pub fn to_gexf(tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>) -> String {
+    let (nodes, edges) = sorted_by_id(tag_graph);
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<gexf xmlns=\"http://gexf.net/1.3\" version=\"1.3\">\n");
+    xml.push_str("  <graph defaultedgetype=\"directed\">\n");
+    xml.push_str("    <attributes class=\"node\">\n");
+    xml.push_str("      <attribute id=\"kind\" title=\"kind\" type=\"string\"/>\n");
+    xml.push_str("      <attribute id=\"path\" title=\"path\" type=\"string\"/>\n");
+    xml.push_str("    </attributes>\n");
+    xml.push_str("    <attributes class=\"edge\">\n");
+    xml.push_str("      <attribute id=\"relation\" title=\"relation\" type=\"string\"/>\n");
+    xml.push_str("    </attributes>\n");
+    xml.push_str("    <nodes>\n");
+    for (id, node) in &nodes {
+        let _ = writeln!(
+            xml,
+            "      <node id=\"{}\" label=\"{}\">",
+            xml_escape(id),
+            xml_escape(&node_label(node))
+        );
+        xml.push_str("        <attvalues>\n");
+        let _ = writeln!(
+            xml,
+            "          <attvalue for=\"kind\" value=\"{}\"/>",
+            node_kind(node)
+        );
+        if let Some(path) = node.path() {
+            let _ = writeln!(
+                xml,
+                "          <attvalue for=\"path\" value=\"{}\"/>",
+                xml_escape(&path.to_string_lossy())
+            );
+        }
+        xml.push_str("        </attvalues>\n");
+        xml.push_str("      </node>\n");
+    }
+    xml.push_str("    </nodes>\n");
+    xml.push_str("    <edges>\n");
+    for (i, (source, target, relation)) in edges.iter().enumerate() {
+        let _ = writeln!(
+            xml,
+            "      <edge id=\"e{}\" source=\"{}\" target=\"{}\" label=\"{:?}\">",
+            i,
+            xml_escape(source),
+            xml_escape(target),
+            relation
+        );
+        let _ = writeln!(
+            xml,
+            "        <attvalues><attvalue for=\"relation\" value=\"{:?}\"/></attvalues>",
+            relation
+        );
+        xml.push_str("      </edge>\n");
+    }
+    xml.push_str("    </edges>\n");
+    xml.push_str("  </graph>\n</gexf>\n");
+    xml
+}
+
+/// An edge between two node ids
+type IdEdge<'a> = (String, String, &'a Relation);
+
+/// Pairs each node with its id from `node_id`, and each edge with the ids of its ends, all sorted
+fn sorted_by_id(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+) -> (Vec<(String, &TagGraphNode)>, Vec<IdEdge<'_>>) {
+    let ids: HashMap<_, String> = tag_graph
+        .graph
+        .node_indices()
+        .map(|idx| (idx, node_id(&tag_graph.graph[idx])))
+        .collect();
+    let mut nodes: Vec<(String, &TagGraphNode)> = tag_graph
+        .graph
+        .node_indices()
+        .map(|idx| (ids[&idx].clone(), &tag_graph.graph[idx]))
+        .collect();
+    nodes.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let mut edges: Vec<IdEdge> = tag_graph
+        .graph
+        .edge_references()
+        .map(|e| {
+            (
+                ids[&e.source()].clone(),
+                ids[&e.target()].clone(),
+                e.weight(),
+            )
+        })
+        .collect();
+    edges.sort();
+    (nodes, edges)
+}
+
+/// Gets an id for a node from its weight, like `file:%2Fphotos%2Fbeach.jpg` or `tag:beach`. Anything that
+/// isn't allowed in an XML name token is percent-encoded, so ids are unique and valid in GraphML.
+fn node_id(node: &TagGraphNode) -> String {
+    let (prefix, name) = match node {
+        TagGraphNode::File { path } => ("file", path.to_string_lossy().to_string()),
+        TagGraphNode::Directory { path } => ("dir", path.to_string_lossy().to_string()),
+        TagGraphNode::Symlink { path, .. } => ("symlink", path.to_string_lossy().to_string()),
+        TagGraphNode::RootDirectory => ("root_dir", String::new()),
+        TagGraphNode::RootTag => ("root_tag", String::new()),
+        TagGraphNode::Tag(tag) => ("tag", tag.clone()),
+        TagGraphNode::TagNamespace(ns) => ("ns", ns.clone()),
+        TagGraphNode::VirtualTag { name, source } => ("virtual", format!("{}:{}", source, name)),
+    };
+    let mut id = String::from(prefix);
+    if !name.is_empty() {
+        id.push(':');
+    }
+    for byte in name.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'.' | b'-' | b'_' => id.push(byte as char),
+            _ => {
+                let _ = write!(id, "%{:02X}", byte);
+            }
+        }
+    }
+    id
+}
+
+/// Escapes text for XML attributes and elements. Control characters XML can't hold are dropped.
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn node_style(node: &TagGraphNode) -> &'static str {
+    match node {
+        TagGraphNode::File { .. } => "shape=note",
+        TagGraphNode::Directory { .. } => "shape=folder, style=filled, fillcolor=\"#fff2cc\"",
+        TagGraphNode::Symlink { .. } => "shape=note, style=dashed",
+        TagGraphNode::RootDirectory | TagGraphNode::RootTag => "shape=doubleoctagon",
+        TagGraphNode::Tag(_) => "shape=ellipse, style=filled, fillcolor=\"#d9ead3\"",
+        TagGraphNode::TagNamespace(_) => "shape=ellipse, style=dashed",
+        TagGraphNode::VirtualTag { .. } => {
+            "shape=ellipse, style=\"filled,dashed\", fillcolor=\"#cfe2f3\""
+        }
+    }
+}
+
+fn edge_style(relation: &Relation) -> &'static str {
+    match relation {
+        Relation::Parent | Relation::Child => "style=solid",
+        Relation::HasTag | Relation::TagAssignedTo => "style=dashed",
+        Relation::InheritedTag | Relation::InheritedTagAssignedTo => "style=dotted",
+        Relation::ParentTag | Relation::ChildTag => "style=bold",
+        Relation::ExcludesTag => "style=dashed, color=red",
+        Relation::HasNamespace | Relation::NamespaceValue => "style=dotted, color=gray",
+        Relation::Alias => "style=solid, color=blue",
+    }
+}
+
+/// Checks whether a relation is the mirror image of another one, which every edge of it comes paired with
+fn is_reverse(relation: &Relation) -> bool {
+    matches!(
+        relation,
+        Relation::Parent
+            | Relation::TagAssignedTo
+            | Relation::InheritedTagAssignedTo
+            | Relation::ChildTag
+            | Relation::NamespaceValue
+    )
+}
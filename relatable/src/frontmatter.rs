@@ -0,0 +1,105 @@
+use std::{fs, path::Path};
+
+use log::{trace, warn};
+use petgraph::Directed;
+use walkdir::WalkDir;
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::{
+    apply_tag_operations, canonicalize_dir, path_node, strip_bom, Error, HashSetGraph, Relation,
+    TagGraphNode, TagOperation,
+};
+
+/// Tags the Markdown files under `root` with the `tags:` from their YAML front matter, like
+/// ```text
+/// ---
+/// title: Hello
+/// tags: [rust, lang:en]
+/// ---
+/// ```
+/// `tags` can be a list, or a string of tags separated by commas or spaces. Files that aren't in the graph
+/// yet are added without a parent, so pass the graph built from `root`. Front matter that isn't valid YAML is
+/// skipped with a warning.
+pub fn add_frontmatter_tags_to_graph(
+    root: impl AsRef<Path>,
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+) -> Result<(), Error> {
+    let root = canonicalize_dir(root.as_ref())?;
+    let tag_root = tag_graph.get_node(&TagGraphNode::RootTag);
+    for entry in WalkDir::new(&root)
+        .sort_by_file_name()
+        .into_iter()
+        .flatten()
+    {
+        let path = entry.path();
+        if !entry.file_type().is_file()
+            || path.extension().and_then(|ext| ext.to_str()) != Some("md")
+        {
+            continue;
+        }
+        let contents = fs::read_to_string(path)?;
+        let Some(frontmatter) = frontmatter(&contents) else {
+            continue;
+        };
+        let operations = match YamlLoader::load_from_str(frontmatter) {
+            Ok(documents) => documents.first().map(frontmatter_tags).unwrap_or_default(),
+            Err(e) => {
+                warn!("Skipping the front matter of {:?}: {}", path, e);
+                continue;
+            }
+        };
+        if operations.is_empty() {
+            continue;
+        }
+
+        trace!("Front matter of {:?} has {:?}", path, operations);
+        let target = match path_node(tag_graph, path) {
+            Some(node) => node,
+            None => tag_graph.get_node_move(TagGraphNode::File {
+                path: path.to_path_buf(),
+            }),
+        };
+        apply_tag_operations(tag_graph, tag_root, path, &operations, &[target]);
+    }
+    Ok(())
+}
+
+/// Gets the YAML between a `---` on the first line and the next `---` or `...` line
+fn frontmatter(contents: &str) -> Option<&str> {
+    let contents = strip_bom(contents);
+    let mut lines = contents.split_inclusive('\n');
+    if lines.next()?.trim_end() != "---" {
+        return None;
+    }
+    let start = contents.find('\n')? + 1;
+    let mut end = start;
+    for line in lines {
+        if matches!(line.trim_end(), "---" | "...") {
+            return Some(&contents[start..end]);
+        }
+        end += line.len();
+    }
+    None
+}
+
+fn frontmatter_tags(document: &Yaml) -> Vec<TagOperation> {
+    let tags: Vec<String> = match &document["tags"] {
+        Yaml::Array(tags) => tags.iter().filter_map(scalar).collect(),
+        Yaml::String(tags) => tags.split([',', ' ', '\t']).map(str::to_string).collect(),
+        tag => scalar(tag).into_iter().collect(),
+    };
+    tags.into_iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .map(TagOperation::Add)
+        .collect()
+}
+
+fn scalar(value: &Yaml) -> Option<String> {
+    match value {
+        Yaml::String(s) | Yaml::Real(s) => Some(s.clone()),
+        Yaml::Integer(i) => Some(i.to_string()),
+        Yaml::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
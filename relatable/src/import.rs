@@ -0,0 +1,464 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use log::{trace, warn};
+use petgraph::Directed;
+#[cfg(any(feature = "tagspaces", feature = "hydrus"))]
+use walkdir::WalkDir;
+
+use crate::{
+    add_tag_node, canonicalize_dir,
+    edit::{add_path_node, sidecar_tagfile, tag_line, write_tagfile},
+    inherit_tags, path_node,
+    tagfile::TagfileDocument,
+    Error, HashSetGraph, Relation, ScanOptions, TagGraphNode, TagOperation,
+};
+
+/// Reads the tags another program keeps for the files under a root, as assignments that can be written with
+/// `apply` or added to a graph with `merge_into_graph`
+pub trait Importer {
+    /// Gets each tagged path under `root` with its tags, in the order they're found
+    fn import(&self, root: &Path) -> Result<Vec<(PathBuf, Vec<String>)>, Error>;
+}
+
+/// How `apply` writes tags to a tag file that already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Adds the imported tags that aren't in the tag file yet, keeping the ones it has
+    Append,
+    /// Replaces the tags in the tag file with the imported ones, like `edit::write_tagfile`
+    Replace,
+}
+
+/// What `apply` did, and which assignments it couldn't apply
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    /// How many tag files were created or changed
+    pub tagfiles_written: usize,
+    /// Paths that don't exist
+    pub missing: Vec<PathBuf>,
+    /// Paths that aren't under the root
+    pub not_under_root: Vec<PathBuf>,
+    /// Tags that can't be written to a tag file, with the path they were for
+    pub invalid_tags: Vec<(PathBuf, String)>,
+    /// Paths whose tag file couldn't be worked out or written, like tag files themselves
+    pub failed: Vec<(PathBuf, Error)>,
+}
+
+/// Reads tag assignments from CSV, e.g. a spreadsheet. Each row is a path followed by its tags, one per
+/// column, and rows for the same path are combined. A header row starting with a `path` column is skipped,
+/// and if it has a `tag` column, only that column is read as tags. Rows with a `source` of `directory` are
+/// skipped, so reading back the output of `export::to_csv` doesn't copy inherited tags onto every file.
+/// Fields can be quoted, with `""` for a quote inside them.
+pub fn from_csv(mut reader: impl io::Read) -> Result<Vec<(PathBuf, Vec<String>)>, Error> {
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    let mut records = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(crate::strip_bom(&contents).as_bytes())
+        .into_records()
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .map(|record| record.iter().map(String::from).collect::<Vec<_>>());
+
+    let (mut tag_column, mut source_column) = (None, None);
+    let mut first = records.next();
+    if let Some(header) = &first {
+        if header
+            .first()
+            .is_some_and(|f| f.trim().eq_ignore_ascii_case("path"))
+        {
+            tag_column = header
+                .iter()
+                .position(|f| f.trim().eq_ignore_ascii_case("tag"));
+            source_column = header
+                .iter()
+                .position(|f| f.trim().eq_ignore_ascii_case("source"));
+            first = None;
+        }
+    }
+
+    let mut assignments = Assignments::default();
+    for record in first.into_iter().chain(records) {
+        let Some((path, rest)) = record.split_first() else {
+            continue;
+        };
+        let inherited = source_column
+            .and_then(|column| record.get(column))
+            .is_some_and(|source| source.trim() == "directory");
+        if path.is_empty() || inherited {
+            continue;
+        }
+        let tags: Vec<&String> = match tag_column {
+            Some(column) => record.get(column).into_iter().collect(),
+            None => rest.iter().collect(),
+        };
+        assignments.add(PathBuf::from(path), tags);
+    }
+    Ok(assignments.into_vec())
+}
+
+/// Collects tag assignments in the order their paths are first seen, combining the tags of a path seen again
+#[derive(Default)]
+struct Assignments {
+    assignments: Vec<(PathBuf, Vec<String>)>,
+    positions: HashMap<PathBuf, usize>,
+}
+
+impl Assignments {
+    /// Adds tags to a path, trimmed and skipping empty or repeated ones
+    fn add<T: AsRef<str>>(&mut self, path: PathBuf, tags: impl IntoIterator<Item = T>) {
+        let position = *self.positions.entry(path.clone()).or_insert_with(|| {
+            self.assignments.push((path, vec![]));
+            self.assignments.len() - 1
+        });
+        let assigned = &mut self.assignments[position].1;
+        for tag in tags {
+            let tag = tag.as_ref().trim();
+            if !tag.is_empty() && !assigned.iter().any(|t| t == tag) {
+                assigned.push(tag.to_string());
+            }
+        }
+    }
+
+    fn into_vec(self) -> Vec<(PathBuf, Vec<String>)> {
+        self.assignments
+    }
+}
+
+/// Writes tag assignments, like the ones from `from_csv`, to the tag files under `root`: a sidecar next to
+/// each file, or the `dir.tags` of a directory, as picked by `edit::sidecar_tagfile`. Relative paths are
+/// relative to `root`. Running the same import again changes nothing. Assignments that can't be applied are
+/// collected in the report instead of stopping the import.
+pub fn apply(
+    root: impl AsRef<Path>,
+    options: &ScanOptions,
+    assignments: &[(PathBuf, Vec<String>)],
+    mode: WriteMode,
+) -> Result<ImportReport, Error> {
+    let root = canonicalize_dir(root.as_ref())?;
+    let mut report = ImportReport::default();
+
+    // Rows for the same path are applied together, so `Replace` keeps all of them
+    let mut combined: Vec<(PathBuf, Vec<&String>)> = vec![];
+    let mut positions: HashMap<PathBuf, usize> = HashMap::new();
+    for (path, tags) in assignments {
+        let path = root.join(path);
+        let Ok(path) = path.canonicalize() else {
+            report.missing.push(path);
+            continue;
+        };
+        if !path.starts_with(&root) {
+            report.not_under_root.push(path);
+            continue;
+        }
+        let position = *positions.entry(path.clone()).or_insert_with(|| {
+            combined.push((path, vec![]));
+            combined.len() - 1
+        });
+        combined[position].1.extend(tags);
+    }
+
+    for (path, tags) in combined {
+        let mut valid: Vec<String> = vec![];
+        for tag in tags {
+            match tag_line(tag) {
+                Ok(_) if !valid.contains(tag) => valid.push(tag.clone()),
+                Ok(_) => {}
+                Err(_) => report.invalid_tags.push((path.clone(), tag.clone())),
+            }
+        }
+        match write_tags(&path, &valid, mode, options) {
+            Ok(true) => report.tagfiles_written += 1,
+            Ok(false) => {}
+            Err(e) => report.failed.push((path, e)),
+        }
+    }
+    Ok(report)
+}
+
+/// Writes a path's imported tags to its tag file. Returns whether the tag file changed.
+fn write_tags(
+    path: &Path,
+    tags: &[String],
+    mode: WriteMode,
+    options: &ScanOptions,
+) -> Result<bool, Error> {
+    let tagfile = sidecar_tagfile(path, options)?;
+    let mut document = TagfileDocument::open(&tagfile)?;
+    let before = document.to_string();
+    match mode {
+        WriteMode::Append => {
+            for tag in tags {
+                if !document.contains(&TagOperation::Add(tag.clone())) {
+                    document.push_line(&tag_line(tag)?);
+                }
+            }
+            if document.to_string() == before || document.is_blank() {
+                return Ok(false);
+            }
+            trace!("Importing tags {:?} into {:?}", tags, tagfile);
+            document.save(&tagfile, false)?;
+        }
+        WriteMode::Replace => {
+            if tags.is_empty() && !tagfile.exists() {
+                return Ok(false);
+            }
+            trace!("Importing tags {:?} into {:?}", tags, tagfile);
+            write_tagfile(&tagfile, tags)?;
+            let after = TagfileDocument::open(&tagfile)?;
+            return Ok(after.to_string() != before);
+        }
+    }
+    Ok(true)
+}
+
+/// Adds tag assignments, like the ones from an `Importer`, to a graph without writing any tag files.
+/// Paths that aren't in the graph yet are added, attached to their parent directory. Paths that don't exist
+/// and tags that can't be added are skipped with a warning. Tags added to directories are inherited by
+/// everything inside them.
+pub fn merge_into_graph(
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+    assignments: &[(PathBuf, Vec<String>)],
+) {
+    let tag_root = tag_graph.get_node(&TagGraphNode::RootTag);
+    let mut tagged_dirs = false;
+    for (path, tags) in assignments {
+        if !path.exists() {
+            warn!("Skipping tags for {:?}, which doesn't exist", path);
+            continue;
+        }
+        let target = match path_node(tag_graph, path) {
+            Some(target) => target,
+            None => add_path_node(tag_graph, path),
+        };
+        for tag in tags {
+            let Some(t) = add_tag_node(tag_graph, tag_root, tag) else {
+                warn!("Skipping the invalid tag {:?} for {:?}", tag, path);
+                continue;
+            };
+            tag_graph.graph.update_edge(target, t, Relation::HasTag);
+            tag_graph
+                .graph
+                .update_edge(t, target, Relation::TagAssignedTo);
+        }
+        tagged_dirs |= path.is_dir() && !tags.is_empty();
+    }
+    if tagged_dirs {
+        inherit_tags(tag_graph);
+    }
+}
+
+/// Imports tags from TagSpaces, which keeps them in two places:
+/// - a JSON sidecar for each file in the `.ts` directory next to it, like `.ts/photo.jpg.json`, and
+///   `.ts/tsm.json` for the directory itself. Tags are listed under `tags`, as objects with a `title` or, in
+///   older versions, as plain strings.
+/// - a bracketed segment before the extension of the file name, like `photo[holiday beach].jpg`
+///
+/// Sidecars that aren't valid JSON, and sidecars for files that no longer exist, are skipped with a warning.
+#[cfg(feature = "tagspaces")]
+#[derive(Debug, Clone)]
+pub struct TagSpaces {
+    tag_delimiter: char,
+}
+
+#[cfg(feature = "tagspaces")]
+impl TagSpaces {
+    pub fn new() -> Self {
+        Self { tag_delimiter: ' ' }
+    }
+
+    /// Sets the character separating tags in file names, which TagSpaces lets you change. Defaults to a space.
+    pub fn tag_delimiter(mut self, tag_delimiter: char) -> Self {
+        self.tag_delimiter = tag_delimiter;
+        self
+    }
+
+    /// Gets the tags in the bracketed segment of a file name
+    fn filename_tags<'a>(&self, name: &'a str) -> Vec<&'a str> {
+        let Some(end) = name.rfind(']') else {
+            return vec![];
+        };
+        let Some(start) = name[..end].rfind('[') else {
+            return vec![];
+        };
+        let rest = &name[end + 1..];
+        if !(rest.is_empty() || rest.starts_with('.')) {
+            return vec![];
+        }
+        name[start + 1..end]
+            .split(self.tag_delimiter)
+            .filter(|tag| !tag.trim().is_empty())
+            .collect()
+    }
+}
+
+#[cfg(feature = "tagspaces")]
+impl Default for TagSpaces {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "tagspaces")]
+impl Importer for TagSpaces {
+    fn import(&self, root: &Path) -> Result<Vec<(PathBuf, Vec<String>)>, Error> {
+        let root = canonicalize_dir(root)?;
+        let mut assignments = Assignments::default();
+        for entry in WalkDir::new(&root)
+            .sort_by_file_name()
+            .into_iter()
+            .flatten()
+        {
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let dir = path
+                .parent()
+                .filter(|dir| dir.file_name().is_some_and(|name| name == ".ts"));
+            match dir.and_then(Path::parent) {
+                Some(tagged_dir) if entry.file_type().is_file() => {
+                    let Some(stem) = name.strip_suffix(".json") else {
+                        continue;
+                    };
+                    let target = match stem {
+                        "tsm" => tagged_dir.to_path_buf(),
+                        _ => tagged_dir.join(stem),
+                    };
+                    if !target.exists() {
+                        warn!("Skipping {:?}, since {:?} doesn't exist", path, target);
+                        continue;
+                    }
+                    let tags = tagspaces_sidecar_tags(path)?;
+                    trace!("TagSpaces sidecar {:?} has {:?}", path, tags);
+                    assignments.add(target, tags);
+                }
+                Some(_) => {}
+                None if entry.file_type().is_file() => {
+                    let tags = self.filename_tags(name);
+                    if !tags.is_empty() {
+                        assignments.add(path.to_path_buf(), tags);
+                    }
+                }
+                None => {}
+            }
+        }
+        Ok(assignments.into_vec())
+    }
+}
+
+/// Reads the tags of a TagSpaces sidecar
+#[cfg(feature = "tagspaces")]
+fn tagspaces_sidecar_tags(path: &Path) -> Result<Vec<String>, Error> {
+    use serde_json::Value;
+
+    let contents = std::fs::read_to_string(path)?;
+    let sidecar: Value = match serde_json::from_str(crate::strip_bom(&contents)) {
+        Ok(sidecar) => sidecar,
+        Err(e) => {
+            warn!("Skipping the TagSpaces sidecar {:?}: {}", path, e);
+            return Ok(vec![]);
+        }
+    };
+    let Some(tags) = sidecar.get("tags").and_then(Value::as_array) else {
+        return Ok(vec![]);
+    };
+    Ok(tags
+        .iter()
+        .filter_map(|tag| match tag {
+            Value::String(title) => Some(title.clone()),
+            Value::Object(tag) => tag.get("title")?.as_str().map(str::to_string),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Imports the tags hydrus exports as a `.txt` sidecar next to each file, like `photo.jpg.txt`, with one tag
+/// per line. Namespaced tags like `creator:foo` become relatable's `namespace:value` tags. A tag hydrus
+/// writes with a leading colon, like `::)` for `:)`, has no namespace, so its colons are escaped as `\:`.
+/// Slashes in tags aren't escaped, so `series:a/b` becomes the hierarchical tag `series:a` / `b`.
+#[cfg(feature = "hydrus")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Hydrus;
+
+#[cfg(feature = "hydrus")]
+impl Importer for Hydrus {
+    fn import(&self, root: &Path) -> Result<Vec<(PathBuf, Vec<String>)>, Error> {
+        let root = canonicalize_dir(root)?;
+        let mut assignments = Assignments::default();
+        for entry in WalkDir::new(&root)
+            .sort_by_file_name()
+            .into_iter()
+            .flatten()
+        {
+            let path = entry.path();
+            if !entry.file_type().is_file() || path.extension().map_or(true, |ext| ext != "txt") {
+                continue;
+            }
+            // Only sidecars of another file, so ordinary text files aren't read as tags
+            let target = path.with_extension("");
+            if !target.is_file() {
+                continue;
+            }
+            let contents = std::fs::read_to_string(path)?;
+            let tags: Vec<String> = crate::strip_bom(&contents)
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(hydrus_tag)
+                .collect();
+            trace!("hydrus sidecar {:?} has {:?}", path, tags);
+            assignments.add(target, tags);
+        }
+        Ok(assignments.into_vec())
+    }
+}
+
+/// Turns a hydrus tag into a relatable one
+#[cfg(feature = "hydrus")]
+fn hydrus_tag(tag: &str) -> String {
+    match tag.trim().strip_prefix(':') {
+        Some(unnamespaced) => unnamespaced.replace(':', "\\:"),
+        None => tag.trim().to_string(),
+    }
+}
+
+/// Reads the tags in a TMSU database, usually `.tmsu/db`. Paths are as TMSU keeps them: absolute, or relative
+/// to the directory holding `.tmsu`, which is the `root` to pass to `apply`. Tags with a value, like
+/// `year=2017`, become `namespace:value` tags, with any colons already in them escaped as `\:`. Tags TMSU
+/// only implies aren't imported.
+#[cfg(feature = "tmsu")]
+pub fn from_tmsu(db_path: impl AsRef<Path>) -> Result<Vec<(PathBuf, Vec<String>)>, Error> {
+    use rusqlite::{Connection, OpenFlags};
+
+    let connection = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut statement = connection.prepare(
+        "SELECT file.directory, file.name, tag.name, value.name
+         FROM file_tag
+         JOIN file ON file.id = file_tag.file_id
+         JOIN tag ON tag.id = file_tag.tag_id
+         LEFT JOIN value ON value.id = file_tag.value_id
+         ORDER BY file.directory, file.name, tag.name, value.name",
+    )?;
+    let mut rows = statement.query([])?;
+    let mut assignments = Assignments::default();
+    while let Some(row) = rows.next()? {
+        let (directory, name, tag, value): (String, String, String, Option<String>) =
+            (row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?);
+        let tag = match value {
+            Some(value) => format!("{}:{}", tmsu_escape(&tag), tmsu_escape(&value)),
+            None => tmsu_escape(&tag),
+        };
+        assignments.add(Path::new(&directory).join(name), [tag]);
+    }
+    Ok(assignments.into_vec())
+}
+
+#[cfg(feature = "tmsu")]
+fn tmsu_escape(name: &str) -> String {
+    name.replace(':', "\\:")
+}
@@ -1,185 +1,2813 @@
 use ::petgraph::stable_graph::StableGraph;
-use glob::glob;
-use log::{error, trace, warn};
+use glob::Pattern;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use log::{info, trace, warn};
 use petgraph::{
-    adj::EdgeIndex,
-    data::Build,
-    graph::{self, NodeIndex},
-    visit::GraphBase,
-    Directed, Graph, Undirected,
+    algo::astar,
+    graph::NodeIndex,
+    visit::{EdgeRef, IntoEdgeReferences},
+    Directed,
 };
 use std::{
-    collections::{hash_set, HashMap, HashSet},
-    fs::{self, File, FileType},
-    io::{self, BufRead},
+    borrow::Cow,
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    fs,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
 };
 use thiserror::Error;
-use walkdir::{DirEntry, WalkDir};
+use walkdir::WalkDir;
+
+#[cfg(feature = "cache")]
+pub mod cache;
+mod diff;
+pub mod edit;
+#[cfg(feature = "exif")]
+mod exif;
+pub mod export;
+#[cfg(feature = "frontmatter")]
+mod frontmatter;
+pub mod import;
+pub mod query;
+pub mod range;
+#[cfg(feature = "serde")]
+mod serialization;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+pub mod tagfile;
+#[cfg(feature = "watch")]
+pub mod watch;
+
+#[cfg(feature = "exif")]
+pub use self::exif::add_exif_tags_to_graph;
+pub use diff::{diff, GraphDiff};
+#[cfg(feature = "frontmatter")]
+pub use frontmatter::add_frontmatter_tags_to_graph;
+#[cfg(feature = "serde")]
+pub use serialization::to_json;
+#[cfg(feature = "sqlite")]
+pub use sqlite::{get_tagged_files_from_db, save_to_db};
 
 pub mod petgraph {
     pub use petgraph::*;
 }
 
-#[derive(Error, Debug)]
-pub enum Error {
-    #[error("oh no! {0}")]
-    OhNo(String),
-    #[error("error msg: {0}")]
-    ErrMsg(&'static str),
-    #[error(transparent)]
-    IO(#[from] std::io::Error),
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+    #[error("couldn't find {path:?}")]
+    PathNotFound {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("{path:?} isn't a directory")]
+    NotADirectory { path: PathBuf },
+    #[error("couldn't read tag file {path:?}")]
+    TagfileRead {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("couldn't parse query at position {position}: {message}")]
+    QueryParse { position: usize, message: String },
+    #[error("{path:?} is a tag file, which can't be tagged")]
+    CannotTagTagfile { path: PathBuf },
+    #[error("{path:?} isn't under {root:?}")]
+    NotUnderRoot { path: PathBuf, root: PathBuf },
+    #[error("{path:?} has no file name")]
+    InvalidPath { path: PathBuf },
+    #[error("{path:?} isn't a file in the graph")]
+    FileNotInGraph { path: PathBuf },
+    #[error("{tag:?} can't be written to a tag file")]
+    InvalidTag { tag: String },
+    #[error("{path:?} inherits {tag:?} from {tagfile:?} instead of having it itself")]
+    TagIsInherited {
+        path: PathBuf,
+        tag: String,
+        tagfile: PathBuf,
+    },
+    #[error("couldn't include tag file {path:?}")]
+    TagfileInclude {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("exclude pattern {pattern:?} isn't valid")]
+    InvalidExclude {
+        pattern: String,
+        #[source]
+        source: globset::Error,
+    },
+    #[error("couldn't scan {path:?}")]
+    Walk {
+        path: PathBuf,
+        #[source]
+        source: walkdir::Error,
+    },
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[cfg(feature = "serde")]
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "cache")]
+    #[error(transparent)]
+    Cache(#[from] bincode::Error),
+    #[cfg(feature = "watch")]
+    #[error(transparent)]
+    Watch(#[from] notify::Error),
+    #[cfg(any(feature = "sqlite", feature = "tmsu"))]
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[cfg(feature = "sqlite")]
+    #[error("the database isn't a saved graph: {message}")]
+    InvalidDatabase { message: String },
+    #[cfg(feature = "sqlite")]
+    #[error("{path:?} isn't valid UTF-8")]
+    NonUtf8Path { path: PathBuf },
+    #[error("couldn't scan {path:?}")]
+    WalkIgnoring {
+        path: PathBuf,
+        #[source]
+        source: ignore::Error,
+    },
+    #[error("couldn't parse {path:?} at byte {offset}: {message}")]
+    JsonTagfile {
+        path: PathBuf,
+        offset: usize,
+        message: String,
+    },
+    #[error("line {line} of {file:?} isn't a valid tag or glob: {content:?}")]
+    TagfileParseError {
+        file: PathBuf,
+        line: usize,
+        content: String,
+    },
+    #[error("the scan was cancelled")]
+    Cancelled,
+    #[error("nodes {first:?} and {second:?} have the same weight")]
+    DuplicateNode { first: NodeIndex, second: NodeIndex },
+}
+
+pub fn get_tagged_files(
+    root: impl AsRef<Path>,
+) -> Result<DirectedHashSetGraph<TagGraphNode, Relation>, Error> {
+    scan(root, &ScanOptions::default()).map(|(graph, _)| graph)
+}
+
+/// Builds one tag graph from several directories, each hanging off `TagGraphNode::RootDirectory`.
+/// A root inside another root is only scanned once, as part of the outer one.
+pub fn get_tagged_files_multi<P: AsRef<Path>>(
+    roots: &[P],
+) -> Result<DirectedHashSetGraph<TagGraphNode, Relation>, Error> {
+    scan_multi(roots, &ScanOptions::default()).map(|(graph, _)| graph)
+}
+
+/// Options for scanning a directory with `scan`
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    tagfile_extension: Cow<'static, str>,
+    follow_symlinks: bool,
+    max_depth: Option<usize>,
+    include_hidden: bool,
+    fail_fast: bool,
+    path_mode: PathMode,
+    symlink_mode: SymlinkMode,
+    use_gitignore: bool,
+    threads: Option<usize>,
+    cancel: Option<Arc<AtomicBool>>,
+    excludes: Vec<String>,
+    /// `excludes`, compiled when scanning starts
+    exclude_set: GlobSet,
+}
+
+/// How the paths of files and directories are stored in a graph
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PathMode {
+    /// Canonical absolute paths
+    #[default]
+    Canonical,
+    /// Paths relative to the scanned root, which is stored as an empty path. A graph like this still makes
+    /// sense after the tree is moved, but paths from different roots of a multi-root scan can collide.
+    RelativeToRoot,
+}
+
+/// Which path the nodes for what's reached through a symbolic link have, when links are followed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkMode {
+    /// The canonical path of the link's target, so something reached through several links is one node
+    #[default]
+    Canonical,
+    /// The path of the link, so something reached through several links has a node for each
+    KeepLinkPath,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            tagfile_extension: Cow::Borrowed("tags"),
+            follow_symlinks: false,
+            max_depth: None,
+            include_hidden: true,
+            fail_fast: false,
+            path_mode: PathMode::Canonical,
+            symlink_mode: SymlinkMode::Canonical,
+            use_gitignore: false,
+            threads: None,
+            cancel: None,
+            excludes: vec![],
+            exclude_set: GlobSet::empty(),
+        }
+    }
+}
+
+impl ScanOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The extension of tag files, without the dot. Defaults to `tags`.
+    /// The other tag file names follow it, e.g. `dir.<ext>`, `_patterns.<ext>` and `manifest.<ext>.tsv`.
+    pub fn tagfile_extension(mut self, extension: impl Into<Cow<'static, str>>) -> Self {
+        self.tagfile_extension = extension.into();
+        self
+    }
+
+    /// Whether to follow symbolic links instead of adding `Symlink` nodes for them. Defaults to false.
+    /// Links that loop back to a directory they're in aren't followed.
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Which paths followed links get. Defaults to `SymlinkMode::Canonical`.
+    pub fn symlink_mode(mut self, symlink_mode: SymlinkMode) -> Self {
+        self.symlink_mode = symlink_mode;
+        self
+    }
+
+    fn canonical_links(&self) -> bool {
+        self.follow_symlinks && self.symlink_mode == SymlinkMode::Canonical
+    }
+
+    /// Gets the path of the node for a path in a scanned directory, which is its target's canonical path
+    /// if it's a link that's followed in `SymlinkMode::Canonical`
+    fn node_path(&self, path: PathBuf) -> PathBuf {
+        if self.canonical_links() && fs::symlink_metadata(&path).is_ok_and(|m| m.is_symlink()) {
+            return path.canonicalize().unwrap_or(path);
+        }
+        path
+    }
+
+    /// How many levels below the root to scan, or None for no limit, which is the default. With 1, only the
+    /// root's own files and directories are scanned. Tag files below the limit aren't read either.
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Whether to scan hidden files and directories, whose names start with a dot, or on Windows, that have
+    /// the hidden attribute. Defaults to true.
+    pub fn include_hidden(mut self, include_hidden: bool) -> Self {
+        self.include_hidden = include_hidden;
+        self
+    }
+
+    /// Whether to stop scanning at the first path that can't be read, or tag file that can't be parsed.
+    /// Otherwise they're skipped with a warning, which is the default.
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Whether paths in the graph are absolute or relative to the root. Defaults to `PathMode::Canonical`.
+    pub fn path_mode(mut self, path_mode: PathMode) -> Self {
+        self.path_mode = path_mode;
+        self
+    }
+
+    /// Whether to skip what `.gitignore` and `.ignore` files and the global git excludes ignore, even outside
+    /// of a git repository. Defaults to false.
+    pub fn use_gitignore(mut self, use_gitignore: bool) -> Self {
+        self.use_gitignore = use_gitignore;
+        self
+    }
+
+    /// How many threads read the file structure and tag files, which are then added to the graph on the
+    /// calling thread, so the graph is the same however many there are. Each root is walked on one thread.
+    /// Defaults to the number of CPUs, and 1 scans everything on the calling thread.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads.max(1));
+        self
+    }
+
+    fn thread_count(&self) -> usize {
+        self.threads.unwrap_or_else(|| {
+            thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+        })
+    }
+
+    /// A flag that stops the scan once it's set, which can be from another thread. The scan then fails with
+    /// `Error::Cancelled` as soon as it's done with the directory entry or tag file it's on.
+    pub fn cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    fn check_cancelled(&self) -> Result<(), Error> {
+        match &self.cancel {
+            Some(cancel) if cancel.load(Ordering::Relaxed) => Err(Error::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Skips files and directories whose names match a glob, like `node_modules` or `*.tmp`.
+    /// Nothing inside an excluded directory is scanned, and excluded paths aren't in the graph even if
+    /// tag files name them.
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.excludes.push(pattern.into());
+        self
+    }
+
+    /// Compiles `excludes`. Fails with `Error::InvalidExclude` for the first pattern that isn't a valid glob.
+    fn compile_excludes(&self) -> Result<ScanOptions, Error> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &self.excludes {
+            let glob = Glob::new(pattern).map_err(|source| Error::InvalidExclude {
+                pattern: pattern.clone(),
+                source,
+            })?;
+            builder.add(glob);
+        }
+        let exclude_set = builder.build().map_err(|source| Error::InvalidExclude {
+            pattern: self.excludes.join(", "),
+            source,
+        })?;
+        Ok(ScanOptions {
+            exclude_set,
+            ..self.clone()
+        })
+    }
+
+    fn is_excluded(&self, name: &std::ffi::OsStr) -> bool {
+        !self.exclude_set.is_empty() && self.exclude_set.is_match(Path::new(name))
+    }
+
+    pub(crate) fn dir_tagfile(&self) -> String {
+        format!("dir.{}", self.tagfile_extension)
+    }
+
+    pub(crate) fn patterns_tagfile(&self) -> String {
+        format!("_patterns.{}", self.tagfile_extension)
+    }
+
+    pub(crate) fn manifest_tagfile(&self) -> String {
+        format!("manifest.{}.tsv", self.tagfile_extension)
+    }
+
+    pub(crate) fn json_tagfile_suffix(&self) -> String {
+        format!(".{}.json", self.tagfile_extension)
+    }
+
+    pub(crate) fn is_json_tagfile(&self, path: &Path) -> bool {
+        path.file_name().is_some_and(|name| {
+            name.as_encoded_bytes()
+                .ends_with(self.json_tagfile_suffix().as_bytes())
+        })
+    }
+
+    /// Checks whether a path is a file tags are read from, or left over from writing one, rather than something to tag.
+    /// A file named just `.tags` has no extension, so it's a hidden file like any other.
+    pub(crate) fn is_tagfile(&self, path: &Path) -> bool {
+        path.extension()
+            .is_some_and(|ext| *ext == *self.tagfile_extension)
+            || self.is_json_tagfile(path)
+            || path
+                .file_name()
+                .is_some_and(|name| *name == *self.manifest_tagfile())
+            || self.is_write_leftover(path)
+    }
+
+    /// Checks whether a path is a `.tmp` or `.bak` file left by `tagfile::write_contents_atomic` writing a
+    /// tag file
+    pub(crate) fn is_write_leftover(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name().map(Path::new) else {
+            return false;
+        };
+        name.extension()
+            .is_some_and(|ext| ext == "tmp" || ext == "bak")
+            && name
+                .file_stem()
+                .is_some_and(|written| self.is_tagfile(&path.with_file_name(written)))
+    }
+
+    /// Gets the name a sidecar tag file is matched to files by: `photo` for `photo.tags`,
+    /// and `photo.jpg` for `photo.jpg.tags.json`. A JSON tag file with nothing before the suffix has none.
+    pub(crate) fn sidecar_stem<'a>(&self, tagfile: &'a Path) -> Option<&'a std::ffi::OsStr> {
+        let name = tagfile.file_name()?;
+        if !self.is_json_tagfile(tagfile) {
+            return tagfile.file_stem();
+        }
+        let suffix = self.json_tagfile_suffix();
+        if name.len() <= suffix.len() {
+            return None;
+        }
+        // Strip the suffix one extension at a time, which works for names that aren't UTF-8
+        let mut stem = name;
+        for _ in 0..suffix.matches('.').count() {
+            stem = Path::new(stem).file_stem()?;
+        }
+        Some(stem)
+    }
+
+    /// Checks whether a scanned path is hidden: its name starts with a dot, or on Windows, it has the hidden
+    /// attribute. The root is never hidden.
+    fn is_hidden(&self, depth: usize, path: &Path) -> bool {
+        if depth == 0 {
+            return false;
+        }
+        if path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        {
+            return true;
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::MetadataExt;
+            const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+            if fs::symlink_metadata(path)
+                .is_ok_and(|m| m.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+            {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Builds a tag graph from the tag files and file structure under a directory.
+/// Tag files are found while walking the directory, so the root is only ever used as a path, and names
+/// with glob characters like `music [flac]` work like any other. Paths matching a `.tagsignore` in a directory
+/// above them are skipped, like excluded ones; see `parse_tagsignore`.
+/// Along with the graph comes a `ScanReport` of what was skipped over and how much was found.
+pub fn scan(
+    root: impl AsRef<Path>,
+    options: &ScanOptions,
+) -> Result<(DirectedHashSetGraph<TagGraphNode, Relation>, ScanReport), Error> {
+    scan_multi(&[root], options)
+}
+
+/// Like `scan`, for several directories at once. See `get_tagged_files_multi`.
+pub fn scan_multi<P: AsRef<Path>>(
+    roots: &[P],
+    options: &ScanOptions,
+) -> Result<(DirectedHashSetGraph<TagGraphNode, Relation>, ScanReport), Error> {
+    let mut tag_graph = DirectedHashSetGraph::<TagGraphNode, Relation>::new();
+    let mut report = ScanReport::default();
+    let options = &options.compile_excludes()?;
+
+    let roots = distinct_roots(roots)?;
+    let entries = walk_roots(&roots, options, &mut report)?;
+    add_scanned_tags(&entries, options, &mut tag_graph, &mut report)?;
+    add_scanned_file_structure(&entries, options, &mut tag_graph)?;
+    // Tag files can name paths the walk skipped, like ignored files next to them
+    let scanned: HashSet<&Path> = entries.iter().map(|e| e.path.as_path()).collect();
+    tag_graph.retain_nodes(|node| match node {
+        TagGraphNode::File { path }
+        | TagGraphNode::Directory { path }
+        | TagGraphNode::Symlink { path, .. } => scanned.contains(path.as_path()),
+        _ => true,
+    });
+    inherit_tags(&mut tag_graph);
+
+    if options.path_mode == PathMode::RelativeToRoot {
+        // Everything is scanned with absolute paths so parents line up, then the roots are stripped at the end
+        tag_graph = map_paths(&tag_graph, |path| {
+            roots
+                .iter()
+                .find_map(|root| path.strip_prefix(root).ok())
+                .unwrap_or(path)
+                .to_path_buf()
+        });
+    }
+
+    for node in tag_graph.graph.node_weights() {
+        match node {
+            TagGraphNode::File { .. } | TagGraphNode::Symlink { .. } => report.files += 1,
+            TagGraphNode::Directory { .. } => report.dirs += 1,
+            TagGraphNode::Tag(_) => report.tags += 1,
+            _ => {}
+        }
+    }
+    Ok((tag_graph, report))
+}
+
+/// What a scan skipped over, and how much it found
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    /// Everything that was skipped with a warning, in the order it was found
+    pub warnings: Vec<ScanWarning>,
+    /// How many files and symbolic links are in the graph
+    pub files: usize,
+    /// How many directories are in the graph, including the roots
+    pub dirs: usize,
+    /// How many different tags are in the graph
+    pub tags: usize,
+    /// How many tag files were read
+    pub tagfiles: usize,
+    /// How many files and directories were excluded, not counting what's inside excluded directories
+    pub excluded: usize,
+}
+
+/// Something a scan skipped over. Each of these is logged as a warning too.
+#[derive(Error, Debug)]
+pub enum ScanWarning {
+    #[error("tag file {path:?} has no associated files")]
+    OrphanTagfile { path: PathBuf },
+    #[error("pattern {pattern} in {tagfile:?} matches nothing")]
+    UnmatchedPattern { tagfile: PathBuf, pattern: String },
+    #[error(
+        "manifest {manifest:?} lists {name:?}, which doesn't exist or can't be tagged from it"
+    )]
+    ManifestEntry { manifest: PathBuf, name: String },
+    #[error("line {line} of {tagfile:?} has an empty or invalid tag")]
+    EmptyTag { tagfile: PathBuf, line: usize },
+    #[error("skipped tag file {path:?}")]
+    SkippedTagfile {
+        path: PathBuf,
+        #[source]
+        source: Error,
+    },
+    #[error("skipped part of the file structure at {path:?}")]
+    WalkError {
+        path: PathBuf,
+        #[source]
+        source: Error,
+    },
+}
+
+/// Adds the files and directories under a root to a graph, each linked to the directory it's in, and the root
+/// to `TagGraphNode::RootDirectory`. This is the first half of what `scan` does; `add_tags_to_graph` adds the
+/// tags, and then `inherit_tags` passes directories' tags down. Add the file structure first, so the paths
+/// tags are added to already have their parents, and use the same root and options for both.
+/// Paths are always absolute, whatever the `PathMode`.
+pub fn add_file_structure_to_graph(
+    root: impl AsRef<Path>,
+    options: &ScanOptions,
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+) -> Result<(), Error> {
+    let options = &options.compile_excludes()?;
+    let entries = walk_roots(
+        &distinct_roots(&[root])?,
+        options,
+        &mut ScanReport::default(),
+    )?;
+    add_scanned_file_structure(&entries, options, tag_graph)
+}
+
+/// Adds the tags from the tag files under a root to a graph. See `add_file_structure_to_graph`, which
+/// should be called first. Like `scan`, tag files that can't be read are skipped with a warning, unless
+/// `ScanOptions::fail_fast` is set. Unlike `scan`, paths the walk skips but tag files name, like ones a
+/// `.gitignore` ignores, are left in the graph.
+pub fn add_tags_to_graph(
+    root: impl AsRef<Path>,
+    options: &ScanOptions,
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+) -> Result<(), Error> {
+    let mut report = ScanReport::default();
+    let options = &options.compile_excludes()?;
+    let entries = walk_roots(&distinct_roots(&[root])?, options, &mut report)?;
+    add_scanned_tags(&entries, options, tag_graph, &mut report)
+}
+
+/// Rebuilds the part of a graph under `subtree` after files or tags in it changed, instead of scanning all of
+/// `root` again. The graph should have been built from `root` by `scan` with the default `ScanOptions`.
+/// Everything under `subtree` is removed and scanned again, then attached to the subtree's parent directory,
+/// whose tag files are read again for the tags of the subtree itself. Tags nothing has anymore are kept;
+/// see `prune_orphan_tags`. The report's counts are for the subtree, except `tags`, which is for the whole graph.
+pub fn rescan_subtree(
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+    root: &Path,
+    subtree: &Path,
+) -> Result<ScanReport, Error> {
+    rescan_subtree_with(tag_graph, root, subtree, &ScanOptions::default())
+}
+
+/// Drops the directories that are inside others in the set
+#[cfg(any(feature = "cache", feature = "watch"))]
+pub(crate) fn outermost(dirs: &BTreeSet<PathBuf>) -> Vec<&PathBuf> {
+    let mut outermost: Vec<&PathBuf> = vec![];
+    // Sorting puts every directory before the ones inside it
+    for dir in dirs {
+        if !outermost.iter().any(|outer| dir.starts_with(outer)) {
+            outermost.push(dir);
+        }
+    }
+    outermost
+}
+
+/// Like `rescan_subtree`, for a graph scanned with `options`, whose paths need to be canonical
+pub(crate) fn rescan_subtree_with(
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+    root: &Path,
+    subtree: &Path,
+    options: &ScanOptions,
+) -> Result<ScanReport, Error> {
+    let options = &options.compile_excludes()?;
+    let root = canonicalize_dir(root)?;
+    let subtree = canonicalize_dir(subtree)?;
+    if !subtree.starts_with(&root) {
+        return Err(Error::NotUnderRoot {
+            path: subtree,
+            root,
+        });
+    }
+
+    let mut report = ScanReport::default();
+    let mut entries = vec![];
+    walk_root(
+        &subtree,
+        &root,
+        options,
+        &mut entries,
+        &mut report.warnings,
+        &mut report.excluded,
+    )?;
+    let parent = subtree
+        .parent()
+        .filter(|_| subtree != root)
+        .map(Path::to_path_buf);
+    if let Some(first) = entries.first_mut() {
+        first.parent = parent.clone();
+    }
+
+    let is_in_subtree = |node: &TagGraphNode| node.path().is_some_and(|p| p.starts_with(&subtree));
+    tag_graph.retain_nodes(|node| !is_in_subtree(node));
+    add_scanned_tags(&entries, options, tag_graph, &mut report)?;
+    add_scanned_file_structure(&entries, options, tag_graph)?;
+    let scanned: HashSet<&Path> = entries.iter().map(|e| e.path.as_path()).collect();
+    tag_graph.retain_nodes(|node| {
+        !is_in_subtree(node) || node.path().is_some_and(|p| scanned.contains(p))
+    });
+
+    // The parent's tag files can tag the subtree too, but they're only reapplied to it, so the order they
+    // were applied to its siblings in isn't disturbed
+    if let Some(parent) = &parent {
+        let subtree_node = TagGraphNode::Directory {
+            path: subtree.clone(),
+        };
+        let tag_root = tag_graph.get_node(&TagGraphNode::RootTag);
+        let siblings: Vec<PathBuf> = fs::read_dir(parent)?.flatten().map(|e| e.path()).collect();
+        for tagfile in ordered_tagfiles(siblings.iter().map(PathBuf::as_path), options) {
+            let targets = match read_tagfile_targets(tagfile, options, &mut vec![]) {
+                Ok(targets) => targets,
+                Err(e) => {
+                    warn!("Skipping tag file {:?}: {}", tagfile, e);
+                    continue;
+                }
+            };
+            let targets = targets
+                .into_iter()
+                .map(|(operations, mut nodes)| {
+                    nodes.retain(|node| *node == subtree_node);
+                    (operations, nodes)
+                })
+                .collect();
+            apply_tagfile_targets(tag_graph, tag_root, tagfile, targets);
+        }
+    }
+    inherit_tags(tag_graph);
+
+    for node in tag_graph.graph.node_weights() {
+        match node {
+            TagGraphNode::File { .. } | TagGraphNode::Symlink { .. } if is_in_subtree(node) => {
+                report.files += 1
+            }
+            TagGraphNode::Directory { .. } if is_in_subtree(node) => report.dirs += 1,
+            TagGraphNode::Tag(_) => report.tags += 1,
+            _ => {}
+        }
+    }
+    Ok(report)
+}
+
+/// Removes the tags that nothing has, or excludes, along with namespaces with no tags left in them.
+/// Parents of hierarchical tags that are still used are kept. Returns how many nodes were removed.
+pub fn prune_orphan_tags(tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>) -> usize {
+    let mut used: HashSet<NodeIndex> = HashSet::new();
+    for edge in tag_graph.graph.edge_references() {
+        match edge.weight() {
+            Relation::TagAssignedTo | Relation::InheritedTagAssignedTo => {
+                used.insert(edge.source());
+            }
+            Relation::ExcludesTag => {
+                used.insert(edge.target());
+            }
+            _ => {}
+        }
+    }
+    let mut to_visit: Vec<NodeIndex> = used.iter().copied().collect();
+    while let Some(node) = to_visit.pop() {
+        for edge in tag_graph.graph.edges(node) {
+            if *edge.weight() == Relation::ParentTag && used.insert(edge.target()) {
+                to_visit.push(edge.target());
+            }
+        }
+    }
+
+    let before = tag_graph.graph.node_count();
+    let graph = &tag_graph.graph;
+    let orphans: HashSet<NodeIndex> = graph
+        .node_indices()
+        .filter(|idx| {
+            matches!(
+                graph[*idx],
+                TagGraphNode::Tag(_) | TagGraphNode::VirtualTag { .. }
+            ) && !used.contains(idx)
+        })
+        .collect();
+    let empty_namespaces: HashSet<NodeIndex> = graph
+        .node_indices()
+        .filter(|&idx| {
+            matches!(graph[idx], TagGraphNode::TagNamespace(_))
+                && graph.edges(idx).all(|e| {
+                    *e.weight() != Relation::NamespaceValue || orphans.contains(&e.target())
+                })
+        })
+        .collect();
+    let removed: HashSet<TagGraphNode> = orphans
+        .iter()
+        .chain(&empty_namespaces)
+        .map(|&idx| graph[idx].clone())
+        .collect();
+    tag_graph.retain_nodes(|node| !removed.contains(node));
+    before - tag_graph.graph.node_count()
+}
+
+/// How much `remove_subtree` took out of a graph
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RemovedStats {
+    pub nodes: usize,
+    pub edges: usize,
+}
+
+/// Removes a file or directory and everything inside it from a graph, without touching the disk, e.g. after
+/// the directory was deleted. With `prune_tags` set, tags left with nothing assigned are removed too, like
+/// `prune_orphan_tags`. A path that isn't in the graph removes nothing.
+pub fn remove_subtree(
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+    path: &Path,
+    prune_tags: bool,
+) -> RemovedStats {
+    let Some(top) = path_node(tag_graph, path) else {
+        return RemovedStats::default();
+    };
+    let (nodes_before, edges_before) = (tag_graph.node_count(), tag_graph.edge_count());
+
+    let mut removed: HashSet<TagGraphNode> = HashSet::new();
+    let mut to_visit = vec![top];
+    while let Some(node) = to_visit.pop() {
+        // Guards against directory cycles
+        if !removed.insert(tag_graph.graph[node].clone()) {
+            continue;
+        }
+        to_visit.extend(
+            tag_graph
+                .graph
+                .edges(node)
+                .filter(|e| *e.weight() == Relation::Child)
+                .map(|e| e.target()),
+        );
+    }
+    tag_graph.retain_nodes(|node| !removed.contains(node));
+    if prune_tags {
+        prune_orphan_tags(tag_graph);
+    }
+
+    RemovedStats {
+        nodes: nodes_before - tag_graph.node_count(),
+        edges: edges_before - tag_graph.edge_count(),
+    }
+}
+
+/// Updates a graph after a file or directory was moved or renamed on disk from `old` to `new`, without
+/// rescanning. The node keeps its tags, and is moved under the directory node for `new`'s parent, which is
+/// added if it isn't in the graph yet. Everything inside a directory is moved along with it. Whatever was at
+/// `new` is replaced, and inherited tags are worked out again with `inherit_tags`.
+/// Returns false if `old` isn't in the graph.
+pub fn move_path(
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+    old: &Path,
+    new: &Path,
+) -> bool {
+    let Some(top) = path_node(tag_graph, old) else {
+        return false;
+    };
+    let old = match tag_graph.graph[top].path() {
+        Some(path) => path.to_path_buf(),
+        None => return false,
+    };
+    let new = fs::canonicalize(new).unwrap_or_else(|_| new.to_path_buf());
+    if old == new {
+        return true;
+    }
+    if path_node(tag_graph, &new).is_some_and(|existing| existing != top) {
+        remove_subtree(tag_graph, &new, false);
+    }
+
+    let mut moved = vec![];
+    let mut visited = HashSet::new();
+    let mut to_visit = vec![top];
+    while let Some(node) = to_visit.pop() {
+        // Guards against directory cycles
+        if !visited.insert(node) {
+            continue;
+        }
+        moved.push(tag_graph.graph[node].clone());
+        to_visit.extend(
+            tag_graph
+                .graph
+                .edges(node)
+                .filter(|e| *e.weight() == Relation::Child)
+                .map(|e| e.target()),
+        );
+    }
+    for node in moved {
+        let Some(path) = node.path() else {
+            continue;
+        };
+        let path = match path.strip_prefix(&old) {
+            Ok(rest) if rest.as_os_str().is_empty() => new.clone(),
+            Ok(rest) => new.join(rest),
+            Err(_) => continue,
+        };
+        let renamed = match &node {
+            TagGraphNode::File { .. } => TagGraphNode::File { path },
+            TagGraphNode::Directory { .. } => TagGraphNode::Directory { path },
+            TagGraphNode::Symlink { target, .. } => TagGraphNode::Symlink {
+                path,
+                target: target.clone(),
+            },
+            _ => continue,
+        };
+        tag_graph.rename_node(&node, renamed);
+    }
+
+    // Roots stay attached to the root directory node
+    let parents: Vec<NodeIndex> = tag_graph
+        .graph
+        .edges(top)
+        .filter(|e| *e.weight() == Relation::Parent)
+        .map(|e| e.target())
+        .filter(|&parent| tag_graph.graph[parent] != TagGraphNode::RootDirectory)
+        .collect();
+    if !parents.is_empty() {
+        tag_graph.graph.retain_edges(|g, e| {
+            let (a, b) = g.edge_endpoints(e).unwrap();
+            !(a == top && parents.contains(&b) || b == top && parents.contains(&a))
+        });
+        if let Some(parent) = new.parent() {
+            let parent = match path_node(tag_graph, parent) {
+                Some(parent) => parent,
+                None => tag_graph.get_node_move(TagGraphNode::Directory {
+                    path: parent.to_path_buf(),
+                }),
+            };
+            tag_graph.graph.update_edge(parent, top, Relation::Child);
+            tag_graph.graph.update_edge(top, parent, Relation::Parent);
+        }
+    }
+    inherit_tags(tag_graph);
+    true
+}
+
+/// Gives every file under `root` a virtual tag for its extension, like `rs` for `main.rs`, with `extension` as
+/// the source. Nothing is read from or written to disk besides finding `root`, so build the graph first.
+/// Files without an extension are left alone.
+pub fn add_virtual_tags_by_extension(
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+    root: impl AsRef<Path>,
+) -> Result<(), Error> {
+    let root = canonicalize_dir(root.as_ref())?;
+    let tag_root = tag_graph.get_node(&TagGraphNode::RootTag);
+    let files: Vec<(NodeIndex, String)> = tag_graph
+        .graph
+        .node_indices()
+        .filter_map(|idx| match &tag_graph.graph[idx] {
+            TagGraphNode::File { path } if path.starts_with(&root) => {
+                let extension = path.extension()?.to_str()?;
+                Some((idx, extension.to_lowercase()))
+            }
+            _ => None,
+        })
+        .collect();
+
+    for (file, extension) in files {
+        let t = tag_graph.get_node_move(TagGraphNode::VirtualTag {
+            name: extension,
+            source: "extension".to_string(),
+        });
+        tag_graph.graph.update_edge(tag_root, t, Relation::HasTag);
+        tag_graph.graph.update_edge(file, t, Relation::HasTag);
+        tag_graph
+            .graph
+            .update_edge(t, file, Relation::TagAssignedTo);
+    }
+    Ok(())
+}
+
+/// Walks each root, which should come from `distinct_roots`, on its own thread
+fn walk_roots(
+    roots: &[PathBuf],
+    options: &ScanOptions,
+    report: &mut ScanReport,
+) -> Result<Vec<ScanEntry>, Error> {
+    let walks = parallel_map(roots, options.thread_count(), |root| {
+        let (mut entries, mut warnings, mut excluded) = (vec![], vec![], 0);
+        if options.use_gitignore {
+            walk_root_ignoring(root, options, &mut entries, &mut warnings, &mut excluded)?;
+        } else {
+            walk_root(
+                root,
+                root,
+                options,
+                &mut entries,
+                &mut warnings,
+                &mut excluded,
+            )?;
+        }
+        Ok::<_, Error>((entries, warnings, excluded))
+    });
+    let mut entries = vec![];
+    for walk in walks {
+        let (root_entries, warnings, excluded) = walk?;
+        entries.extend(root_entries);
+        report.warnings.extend(warnings);
+        report.excluded += excluded;
+    }
+
+    if report.excluded > 0 {
+        info!("Skipped {} excluded files and directories", report.excluded);
+    }
+    Ok(entries)
+}
+
+/// A file or directory found while walking a root
+struct ScanEntry {
+    /// The path of its node in the graph
+    path: PathBuf,
+    /// The path of the directory it was found in, which is None for the root
+    parent: Option<PathBuf>,
+    is_file: bool,
+}
+
+/// Calls `f` on each item on up to `threads` threads, returning the results in the same order as the items
+fn parallel_map<T, R>(items: &[T], threads: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+{
+    let threads = threads.min(items.len());
+    if threads <= 1 {
+        return items.iter().map(f).collect();
+    }
+    // Threads take the next item as they finish, since some take much longer than others
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(i) else {
+                            break results;
+                        };
+                        results.push((i, f(item)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Works out the paths of the nodes for what's walked. They're the walked paths, unless links are followed
+/// in `SymlinkMode::Canonical`, where everything reached through a link is under the link's canonical target.
+struct ScanPaths<'a> {
+    options: &'a ScanOptions,
+    /// Walked directories whose nodes have a different path
+    moved_dirs: HashMap<PathBuf, PathBuf>,
+    /// The node paths of the directories that have been walked
+    visited_dirs: HashSet<PathBuf>,
+}
+
+impl<'a> ScanPaths<'a> {
+    fn new(options: &'a ScanOptions) -> Self {
+        ScanPaths {
+            options,
+            moved_dirs: HashMap::new(),
+            visited_dirs: HashSet::new(),
+        }
+    }
+
+    /// Adds a walked entry. Returns false if it's a directory that was already walked through another path,
+    /// which shouldn't be walked again.
+    fn push(
+        &mut self,
+        entries: &mut Vec<ScanEntry>,
+        walked: PathBuf,
+        depth: usize,
+        is_file: bool,
+        is_symlink: bool,
+    ) -> bool {
+        let walked_parent = walked.parent().filter(|_| depth > 0);
+        let moved_parent = walked_parent.and_then(|parent| self.moved_dirs.get(parent));
+        let path = match (moved_parent, walked.file_name()) {
+            // Tag files keep their name, since it says what they apply to
+            _ if self.options.canonical_links()
+                && is_symlink
+                && !self.options.is_tagfile(&walked) =>
+            {
+                walked.canonicalize().unwrap_or_else(|_| walked.clone())
+            }
+            (Some(moved_parent), Some(name)) => moved_parent.join(name),
+            _ => walked.clone(),
+        };
+        let parent = moved_parent
+            .cloned()
+            .or_else(|| walked_parent.map(Path::to_path_buf));
+
+        let mut walk_inside = true;
+        if !is_file {
+            if path != walked {
+                self.moved_dirs.insert(walked, path.clone());
+            }
+            walk_inside = self.visited_dirs.insert(path.clone()) || !self.options.canonical_links();
+        }
+        entries.push(ScanEntry {
+            path,
+            parent,
+            is_file,
+        });
+        walk_inside
+    }
+}
+
+/// Walks a root. `.tagsignore` files count from `tagsignore_root` down, which is usually the root itself.
+fn walk_root(
+    root: &Path,
+    tagsignore_root: &Path,
+    options: &ScanOptions,
+    entries: &mut Vec<ScanEntry>,
+    warnings: &mut Vec<ScanWarning>,
+    excluded: &mut usize,
+) -> Result<(), Error> {
+    let mut walk = WalkDir::new(root)
+        .follow_links(options.follow_symlinks)
+        .sort_by_file_name();
+    if let Some(max_depth) = options.max_depth {
+        walk = walk.max_depth(max_depth);
+    }
+    let mut paths = ScanPaths::new(options);
+    let tagsignore = TagsIgnore::new(tagsignore_root);
+    let mut walk = walk.into_iter().filter_entry(|e| {
+        if e.depth() > 0 && (options.is_excluded(e.file_name()) || tagsignore.is_ignored(e.path()))
+        {
+            *excluded += 1;
+            return false;
+        }
+        options.include_hidden || !options.is_hidden(e.depth(), e.path())
+    });
+    while let Some(entry) = walk.next() {
+        options.check_cancelled()?;
+        match entry {
+            Ok(entry) => {
+                let (depth, is_file, is_symlink) = (
+                    entry.depth(),
+                    entry.file_type().is_file(),
+                    entry.path_is_symlink(),
+                );
+                if !paths.push(entries, entry.into_path(), depth, is_file, is_symlink) {
+                    walk.skip_current_dir();
+                }
+            }
+            Err(e) if e.loop_ancestor().is_some() => {
+                trace!("Not following a symbolic link loop: {}", e);
+            }
+            Err(source) => {
+                let path = source.path().unwrap_or(root).to_path_buf();
+                let e = Error::Walk {
+                    path: path.clone(),
+                    source,
+                };
+                if options.fail_fast {
+                    return Err(e);
+                }
+                warn!("Skipping part of the file structure: {}", e);
+                warnings.push(ScanWarning::WalkError { path, source: e });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Like `walk_root`, skipping what `.gitignore` and `.ignore` files and the global git excludes ignore
+fn walk_root_ignoring(
+    root: &Path,
+    options: &ScanOptions,
+    entries: &mut Vec<ScanEntry>,
+    warnings: &mut Vec<ScanWarning>,
+    excluded: &mut usize,
+) -> Result<(), Error> {
+    let excluded_count = Arc::new(AtomicUsize::new(0));
+    let mut walk = ignore::WalkBuilder::new(root);
+    walk.follow_links(options.follow_symlinks)
+        .max_depth(options.max_depth)
+        .hidden(!options.include_hidden)
+        // Tag roots usually aren't git repositories, and their .gitignore files should still count
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b));
+    {
+        let exclude_options = options.clone();
+        let excluded_count = excluded_count.clone();
+        let tagsignore = TagsIgnore::new(root);
+        walk.filter_entry(move |e| {
+            let skip = e.depth() > 0
+                && (exclude_options.is_excluded(e.file_name()) || tagsignore.is_ignored(e.path()));
+            if skip {
+                excluded_count.fetch_add(1, Ordering::Relaxed);
+            }
+            !skip
+        });
+    }
+    // The ignore crate can't skip a directory from outside its filter, so a directory reached through
+    // several links is walked each time, though its nodes are only added once
+    let mut paths = ScanPaths::new(options);
+    for entry in walk.build() {
+        options.check_cancelled()?;
+        match entry {
+            Ok(entry) => {
+                let (depth, is_file, is_symlink) = (
+                    entry.depth(),
+                    entry.file_type().is_some_and(|t| t.is_file()),
+                    entry.path_is_symlink(),
+                );
+                paths.push(entries, entry.into_path(), depth, is_file, is_symlink);
+            }
+            Err(e) if is_ignore_loop(&e) => {
+                trace!("Not following a symbolic link loop: {}", e);
+            }
+            Err(source) => {
+                let path = ignore_error_path(&source).unwrap_or(root).to_path_buf();
+                let e = Error::WalkIgnoring {
+                    path: path.clone(),
+                    source,
+                };
+                if options.fail_fast {
+                    return Err(e);
+                }
+                warn!("Skipping part of the file structure: {}", e);
+                warnings.push(ScanWarning::WalkError { path, source: e });
+            }
+        }
+    }
+    *excluded += excluded_count.load(Ordering::Relaxed);
+    Ok(())
+}
+
+/// Gets the path an error from the ignore crate is about, if it says
+fn ignore_error_path(e: &ignore::Error) -> Option<&Path> {
+    match e {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            ignore_error_path(err)
+        }
+        _ => None,
+    }
+}
+
+fn is_ignore_loop(e: &ignore::Error) -> bool {
+    match e {
+        ignore::Error::Loop { .. } => true,
+        ignore::Error::WithPath { err, .. }
+        | ignore::Error::WithDepth { err, .. }
+        | ignore::Error::WithLineNumber { err, .. } => is_ignore_loop(err),
+        _ => false,
+    }
+}
+
+/// Copies a graph, changing the path of every file, directory and symbolic link
+fn map_paths(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    f: impl Fn(&Path) -> PathBuf,
+) -> HashSetGraph<TagGraphNode, Relation, Directed> {
+    let mut mapped = HashSetGraph::new();
+    let indices: HashMap<NodeIndex, NodeIndex> = tag_graph
+        .graph
+        .node_indices()
+        .map(|idx| {
+            let node = match &tag_graph.graph[idx] {
+                TagGraphNode::File { path } => TagGraphNode::File { path: f(path) },
+                TagGraphNode::Directory { path } => TagGraphNode::Directory { path: f(path) },
+                TagGraphNode::Symlink { path, target } => TagGraphNode::Symlink {
+                    path: f(path),
+                    target: target.clone(),
+                },
+                node => node.clone(),
+            };
+            (idx, mapped.get_node_move(node))
+        })
+        .collect();
+    for edge in tag_graph.graph.edge_references() {
+        let (a, b) = (indices[&edge.source()], indices[&edge.target()]);
+        // Paths that collide are merged into one node, so don't duplicate their edges
+        if !mapped
+            .graph
+            .edges_connecting(a, b)
+            .any(|e| e.weight() == edge.weight())
+        {
+            mapped.graph.add_edge(a, b, edge.weight().clone());
+        }
+    }
+    mapped
+}
+
+/// Canonicalizes a path, failing with `Error::PathNotFound` if it doesn't exist
+pub(crate) fn canonicalize(path: &Path) -> Result<PathBuf, Error> {
+    path.canonicalize().map_err(|source| Error::PathNotFound {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Like `canonicalize`, failing with `Error::NotADirectory` if the path isn't a directory
+pub(crate) fn canonicalize_dir(path: &Path) -> Result<PathBuf, Error> {
+    let canonical = canonicalize(path)?;
+    if !canonical.is_dir() {
+        return Err(Error::NotADirectory {
+            path: path.to_path_buf(),
+        });
+    }
+    Ok(canonical)
+}
+
+/// Reads a tag file's contents, failing with `Error::TagfileRead`
+pub(crate) fn read_tagfile_contents(path: &Path) -> Result<String, Error> {
+    fs::read_to_string(path).map_err(|source| Error::TagfileRead {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Canonicalizes roots, dropping duplicates and roots inside other roots so nothing gets walked twice
+fn distinct_roots<P: AsRef<Path>>(roots: &[P]) -> Result<Vec<PathBuf>, Error> {
+    let mut canonical = roots
+        .iter()
+        .map(|root| canonicalize_dir(root.as_ref()))
+        .collect::<Result<Vec<_>, _>>()?;
+    // Sorting puts every root before the roots inside it
+    canonical.sort();
+    let mut distinct: Vec<PathBuf> = vec![];
+    for root in canonical {
+        if !distinct.iter().any(|outer| root.starts_with(outer)) {
+            distinct.push(root);
+        }
+    }
+    Ok(distinct)
+}
+
+fn add_scanned_tags(
+    entries: &[ScanEntry],
+    options: &ScanOptions,
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+    report: &mut ScanReport,
+) -> Result<(), Error> {
+    let tag_root = tag_graph.get_node(&TagGraphNode::RootTag);
+    let tagfiles = ordered_tagfiles(
+        entries
+            .iter()
+            .filter(|e| e.is_file)
+            .map(|e| e.path.as_path()),
+        options,
+    );
+
+    let read = parallel_map(&tagfiles, options.thread_count(), |tagfile| {
+        options.check_cancelled()?;
+        let mut warnings = vec![];
+        let targets = read_tagfile_targets(tagfile, options, &mut warnings);
+        Ok::<_, Error>((targets, warnings))
+    });
+    for (tagfile, read) in tagfiles.iter().zip(read) {
+        let (targets, warnings) = read?;
+        if let Some(dir) = tagfile.parent() {
+            // Only added so the directory comes before what its tag files tag
+            let _ = tag_graph.get_node_move(TagGraphNode::Directory {
+                path: dir.to_path_buf(),
+            });
+        }
+        report.warnings.extend(warnings);
+        match targets {
+            Ok(targets) => {
+                apply_tagfile_targets(tag_graph, tag_root, tagfile, targets);
+                report.tagfiles += 1;
+            }
+            Err(e) if !options.fail_fast => {
+                warn!("Skipping tag file {:?}: {}", tagfile, e);
+                report.warnings.push(ScanWarning::SkippedTagfile {
+                    path: tagfile.to_path_buf(),
+                    source: e,
+                });
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Picks out the tag files from some paths, in the order they're applied: plain tag files first, then
+/// manifests, then JSON sidecars
+fn ordered_tagfiles<'a>(
+    paths: impl Iterator<Item = &'a Path>,
+    options: &ScanOptions,
+) -> Vec<&'a Path> {
+    let manifest = options.manifest_tagfile();
+    let mut ordered: Vec<(u8, &Path)> = paths
+        .filter(|path| options.is_tagfile(path) && !options.is_write_leftover(path))
+        .filter_map(|tagfile| {
+            if options.is_json_tagfile(tagfile) {
+                cfg!(feature = "serde").then_some((2, tagfile))
+            } else if tagfile.file_name().is_some_and(|name| *name == *manifest) {
+                Some((1, tagfile))
+            } else {
+                Some((0, tagfile))
+            }
+        })
+        .collect();
+    ordered.sort_by_key(|(order, _)| *order);
+    ordered.into_iter().map(|(_, tagfile)| tagfile).collect()
+}
+
+/// The tag operations in a tag file, each with the nodes it applies to
+type TagfileTargets = Vec<(Vec<TagOperation>, Vec<TagGraphNode>)>;
+
+/// Reads one tag file and works out what its tags apply to, without touching the graph.
+/// What's skipped along the way is added to `warnings`.
+fn read_tagfile_targets(
+    tagfile: &Path,
+    options: &ScanOptions,
+    warnings: &mut Vec<ScanWarning>,
+) -> Result<TagfileTargets, Error> {
+    trace!("Visiting tagfile {}", tagfile.to_string_lossy());
+    let Some(dirpath) = tagfile.parent() else {
+        return Err(Error::InvalidPath {
+            path: tagfile.to_path_buf(),
+        });
+    };
+    let dirpath = dirpath.to_path_buf();
+    let Some(name) = tagfile.file_name() else {
+        return Ok(vec![]);
+    };
+    if *name == *options.patterns_tagfile() {
+        let mut targets = vec![];
+        for (pattern, operations) in read_patterns(tagfile, warnings)? {
+            let mut matched_nodes = vec![];
+            for file_path in taggable_entries(&dirpath, options)? {
+                // Names that aren't UTF-8 are matched with their invalid parts replaced
+                let matched = file_path
+                    .file_name()
+                    .is_some_and(|n| pattern.matches(&n.to_string_lossy()));
+                if matched {
+                    matched_nodes.push(scanned_path_node(file_path, options));
+                }
+            }
+            if matched_nodes.is_empty() {
+                warn!("Pattern {} in {:?} matches nothing", pattern, tagfile);
+                warnings.push(ScanWarning::UnmatchedPattern {
+                    tagfile: tagfile.to_path_buf(),
+                    pattern: pattern.to_string(),
+                });
+            }
+            targets.push((operations, matched_nodes));
+        }
+        return Ok(targets);
+    }
+    if *name == *options.manifest_tagfile() {
+        let mut targets = vec![];
+        for (file_name, operations) in read_manifest(tagfile, warnings)? {
+            let file_path = dirpath.join(&file_name);
+            if file_path.parent() != Some(&dirpath)
+                || options.is_tagfile(&file_path)
+                || file_path
+                    .file_name()
+                    .is_some_and(|name| options.is_excluded(name))
+            {
+                warn!(
+                    "Manifest {:?} lists {:?}, which can't be tagged from it",
+                    tagfile, file_name
+                );
+                warnings.push(ScanWarning::ManifestEntry {
+                    manifest: tagfile.to_path_buf(),
+                    name: file_name,
+                });
+                continue;
+            }
+            if fs::symlink_metadata(&file_path).is_err() {
+                warn!(
+                    "Manifest {:?} lists {:?}, which doesn't exist",
+                    tagfile, file_name
+                );
+                warnings.push(ScanWarning::ManifestEntry {
+                    manifest: tagfile.to_path_buf(),
+                    name: file_name,
+                });
+                continue;
+            }
+            targets.push((operations, vec![scanned_path_node(file_path, options)]));
+        }
+        return Ok(targets);
+    }
+
+    // Collect the tag attach targets
+    let mut tag_attach_targets: Vec<TagGraphNode> = vec![];
+    if *name == *options.dir_tagfile() {
+        trace!("This is a directory tagfile. attach target: {:?}", dirpath);
+        tag_attach_targets.push(TagGraphNode::Directory {
+            path: dirpath.clone(),
+        });
+    } else if let Some(tagfile_stem) = options.sidecar_stem(tagfile) {
+        // Files with the matching name
+        for file_path in taggable_entries(&dirpath, options)? {
+            if file_path.file_stem() == Some(tagfile_stem)
+                || file_path.file_name() == Some(tagfile_stem)
+            {
+                trace!("Found file {}", file_path.to_string_lossy());
+                tag_attach_targets.push(scanned_path_node(file_path, options));
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    let operations = if options.is_json_tagfile(tagfile) {
+        tagfile::read_json_tagfile(tagfile)?
+    } else {
+        read_tagfile_with_includes(tagfile)?
+    };
+    #[cfg(not(feature = "serde"))]
+    let operations = read_tagfile_with_includes(tagfile)?;
+    // `glob:` lines bring in more files from the same directory
+    let globs: Vec<&Pattern> = operations
+        .iter()
+        .filter_map(|operation| match operation {
+            TagOperation::Glob(pattern) => Some(pattern),
+            _ => None,
+        })
+        .collect();
+    if !globs.is_empty() {
+        for file_path in taggable_entries(&dirpath, options)? {
+            let matched = file_path.file_name().is_some_and(|n| {
+                let n = n.to_string_lossy();
+                globs.iter().any(|pattern| pattern.matches(&n))
+            });
+            if matched {
+                let node = scanned_path_node(file_path, options);
+                if !tag_attach_targets.contains(&node) {
+                    tag_attach_targets.push(node);
+                }
+            }
+        }
+    }
+    if tag_attach_targets.is_empty() {
+        warn!("Tag file {:?} has no associated files", tagfile);
+        warnings.push(ScanWarning::OrphanTagfile {
+            path: tagfile.to_path_buf(),
+        });
+    }
+    Ok(vec![(operations, tag_attach_targets)])
+}
+
+/// Adds the nodes a tag file applies to, and attaches its tags to them
+fn apply_tagfile_targets(
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+    tag_root: NodeIndex,
+    tagfile: &Path,
+    targets: TagfileTargets,
+) {
+    for (operations, nodes) in targets {
+        let nodes: Vec<NodeIndex> = nodes
+            .into_iter()
+            .map(|node| tag_graph.get_node_move(node))
+            .collect();
+        apply_tag_operations(tag_graph, tag_root, tagfile, &operations, &nodes);
+    }
+}
+
+/// The end of the name of a JSON sidecar with the default tag file extension, e.g. `photo.jpg.tags.json`.
+/// It follows `ScanOptions::tagfile_extension`.
+pub const JSON_TAGFILE_SUFFIX: &str = ".tags.json";
+
+/// Gets the paths in a directory that tag files can apply to, which is everything but tag files
+fn taggable_entries(dir: &Path, options: &ScanOptions) -> Result<Vec<PathBuf>, Error> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        // Don't associate a tagfile with itself
+        if options.is_tagfile(&path) {
+            continue;
+        }
+        if !options.include_hidden && options.is_hidden(1, &path) {
+            continue;
+        }
+        if options.is_excluded(&entry.file_name()) {
+            continue;
+        }
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+fn apply_tag_operations(
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+    tag_root: NodeIndex,
+    tagfile: &Path,
+    operations: &[TagOperation],
+    tag_attach_targets: &[NodeIndex],
+) {
+    for operation in operations {
+        trace!("Tagfile contains {:?}", operation);
+        let (tag, add) = match operation {
+            TagOperation::Add(tag) => (tag, true),
+            TagOperation::Remove(tag) => (tag, false),
+            TagOperation::Glob(_) => continue,
+        };
+        let Some(t) = add_tag_node(tag_graph, tag_root, tag) else {
+            warn!("Tag file {:?} contains an invalid tag {:?}", tagfile, tag);
+            continue;
+        };
+        for attach_target in tag_attach_targets {
+            if add {
+                trace!("Attaching tag {:?} to {:?}", t, attach_target);
+                tag_graph
+                    .graph
+                    .update_edge(*attach_target, t, Relation::HasTag);
+                tag_graph
+                    .graph
+                    .update_edge(t, *attach_target, Relation::TagAssignedTo);
+            } else {
+                trace!("Excluding tag {:?} from {:?}", t, attach_target);
+                // Drop the tag if another tagfile already assigned it directly
+                if let Some(e) = tag_graph.graph.find_edge(*attach_target, t) {
+                    tag_graph.graph.remove_edge(e);
+                }
+                if let Some(e) = tag_graph.graph.find_edge(t, *attach_target) {
+                    tag_graph.graph.remove_edge(e);
+                }
+                tag_graph
+                    .graph
+                    .update_edge(*attach_target, t, Relation::ExcludesTag);
+            }
+        }
+    }
+}
+
+/// Adds a tag node linked to the root tag, and returns its index.
+/// Hierarchical tags like `art/sketches/ink` also get nodes for each of their parents (`art`, `art/sketches`).
+/// Namespaced tags like `rating:5` are also linked to a node for their namespace.
+/// Returns None if the tag has no name once empty segments are dropped.
+fn add_tag_node(
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+    tag_root: NodeIndex,
+    tag: &str,
+) -> Option<NodeIndex> {
+    let mut name = String::new();
+    let mut parent: Option<NodeIndex> = None;
+    for segment in tag.split('/').filter(|s| !s.is_empty()) {
+        if !name.is_empty() {
+            name.push('/');
+        }
+        name.push_str(segment);
+        // Kept as written, so `a\\:b` stays a different tag from the namespaced `a:b`
+        let t = tag_graph.get_node_move(TagGraphNode::Tag(name.clone()));
+        tag_graph.graph.update_edge(tag_root, t, Relation::HasTag);
+        if let Some(parent) = parent {
+            tag_graph.graph.update_edge(t, parent, Relation::ParentTag);
+            tag_graph.graph.update_edge(parent, t, Relation::ChildTag);
+        }
+        if let Some((namespace, _)) = split_namespace(&name) {
+            let ns = tag_graph.get_node_move(TagGraphNode::TagNamespace(namespace));
+            tag_graph.graph.update_edge(t, ns, Relation::HasNamespace);
+            tag_graph.graph.update_edge(ns, t, Relation::NamespaceValue);
+        }
+        parent = Some(t);
+    }
+    parent
+}
+
+/// Splits a `namespace:value` tag at its first colon. Colons escaped as `\:` don't count.
+/// Returns None for tags without a namespace, or with an empty namespace or value.
+pub fn split_namespace(tag: &str) -> Option<(String, String)> {
+    let mut escaped = false;
+    for (i, c) in tag.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            ':' if !escaped => {
+                let (namespace, value) = (&tag[..i], &tag[i + 1..]);
+                if namespace.is_empty() || value.is_empty() {
+                    return None;
+                }
+                return Some((namespace.replace("\\:", ":"), value.replace("\\:", ":")));
+            }
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+fn add_scanned_file_structure(
+    entries: &[ScanEntry],
+    options: &ScanOptions,
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+) -> Result<(), Error> {
+    let dir_root = tag_graph.get_node(&TagGraphNode::RootDirectory);
+    // Working out the nodes takes a call to the filesystem each, so it's done before adding them
+    let nodes = parallel_map(entries, options.thread_count(), |entry| {
+        options.check_cancelled()?;
+        Ok::<_, Error>(
+            (entry.parent.is_some() && !options.is_tagfile(&entry.path))
+                .then(|| scanned_path_node(entry.path.clone(), options)),
+        )
+    });
+    for (entry, node) in entries.iter().zip(nodes) {
+        let node = node?;
+        let path = entry.path.clone();
+        if options.is_tagfile(&path) {
+            continue;
+        }
+
+        match (&entry.parent, node) {
+            (None, _) => {
+                let node = tag_graph.get_node_move(TagGraphNode::Directory { path });
+                tag_graph.graph.update_edge(dir_root, node, Relation::Child);
+                tag_graph
+                    .graph
+                    .update_edge(node, dir_root, Relation::Parent);
+            }
+            (Some(parent), Some(node)) => {
+                let parent = tag_graph.get_node_move(TagGraphNode::Directory {
+                    path: parent.clone(),
+                });
+                let node = tag_graph.get_node_move(node);
+                tag_graph.graph.update_edge(parent, node, Relation::Child);
+                tag_graph.graph.update_edge(node, parent, Relation::Parent);
+            }
+            (Some(_), None) => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Gets the node for a path found while scanning. Unless links are followed, symbolic links get a `Symlink`
+/// node. Followed links get a node for their target, with the path from `ScanOptions::node_path`.
+fn scanned_path_node(path: PathBuf, options: &ScanOptions) -> TagGraphNode {
+    let follow_symlinks = options.follow_symlinks;
+    let path = options.node_path(path);
+    let metadata = if follow_symlinks {
+        fs::metadata(&path)
+    } else {
+        fs::symlink_metadata(&path)
+    };
+    match metadata {
+        Ok(metadata) if metadata.is_symlink() => {
+            let target = fs::read_link(&path).unwrap_or_default();
+            TagGraphNode::Symlink { path, target }
+        }
+        Ok(metadata) if metadata.is_dir() => TagGraphNode::Directory { path },
+        _ => TagGraphNode::File { path },
+    }
+}
+
+/// A line of a tag file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagOperation {
+    /// Assigns the tag
+    Add(String),
+    /// Cancels the tag where it would otherwise be inherited from a directory
+    Remove(String),
+    /// Applies the tag file's other lines to the files in its directory matching the glob as well
+    Glob(Pattern),
+}
+
+/// Copies the tags of each directory down to everything inside it, so that descendants have their own
+/// `InheritedTag`/`InheritedTagAssignedTo` edges to them. Tags a descendant excludes aren't copied to it or
+/// below it. Inherited edges from an earlier run are replaced. Needs the file structure to already be in the graph.
+pub fn inherit_tags(tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>) {
+    tag_graph.graph.retain_edges(|g, e| {
+        !matches!(
+            g[e],
+            Relation::InheritedTag | Relation::InheritedTagAssignedTo
+        )
+    });
+    let Some(&dir_root) = tag_graph.map.get(&TagGraphNode::RootDirectory) else {
+        return;
+    };
+
+    let mut inherited_edges = vec![];
+    let mut visited = HashSet::new();
+    let mut to_visit = vec![(dir_root, Vec::<NodeIndex>::new())];
+    while let Some((node, inherited)) = to_visit.pop() {
+        if !visited.insert(node) {
+            continue;
+        }
+        let mut own = vec![];
+        let mut excluded = HashSet::new();
+        for edge in tag_graph.graph.edges(node) {
+            match edge.weight() {
+                Relation::HasTag => own.push(edge.target()),
+                Relation::ExcludesTag => {
+                    excluded.insert(edge.target());
+                }
+                _ => (),
+            }
+        }
+
+        let mut tags: Vec<NodeIndex> = inherited
+            .into_iter()
+            .filter(|t| {
+                !tag_ancestry(tag_graph, *t)
+                    .iter()
+                    .any(|a| excluded.contains(a))
+            })
+            .collect();
+        for t in &tags {
+            if !own.contains(t) {
+                inherited_edges.push((node, *t));
+            }
+        }
+        for t in own {
+            if !tags.contains(&t) {
+                tags.push(t);
+            }
+        }
+
+        for edge in tag_graph.graph.edges(node) {
+            if *edge.weight() == Relation::Child {
+                to_visit.push((edge.target(), tags.clone()));
+            }
+        }
+    }
+
+    for (node, t) in inherited_edges {
+        trace!("Inheriting tag {:?} to {:?}", t, node);
+        tag_graph.graph.update_edge(node, t, Relation::InheritedTag);
+        tag_graph
+            .graph
+            .update_edge(t, node, Relation::InheritedTagAssignedTo);
+    }
+}
+
+/// Options for reading tag files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagfileOptions {
+    /// Whether to trim whitespace around each line. Without this, only the line ending is removed,
+    /// so tags can start or end with spaces.
+    pub trim_whitespace: bool,
+}
+
+impl Default for TagfileOptions {
+    fn default() -> Self {
+        TagfileOptions {
+            trim_whitespace: true,
+        }
+    }
+}
+
+/// Reads a tag file
+/// A tag file is simply a text file where each line is a tag
+/// Surrounding whitespace is trimmed, and blank lines and lines starting with `#` are skipped
+/// A line starting with `-` or `!` removes the tag instead. Tags that really start with one of those,
+/// or with `#` or `@`, are escaped with a backslash, e.g. `\-tag` or `\#1`
+/// `@include` lines are skipped; see `read_tagfile_with_includes`
+/// A `glob:<pattern>` line makes the tags apply to the files in the same directory matching the glob too,
+/// e.g. `glob:*.rs`. Tags that really start with `glob:` are escaped like `\glob:tag`
+/// Both `\n` and `\r\n` line endings are accepted, and a UTF-8 byte order mark is skipped
+/// Fails with `Error::TagfileParseError` for a line with an invalid glob, or a tag that's only slashes or
+/// has control characters in it
+pub fn read_tagfile(file: &Path) -> Result<Vec<TagOperation>, Error> {
+    read_tagfile_with(file, &TagfileOptions::default())
+}
+
+/// Like `read_tagfile`, with options for how lines are read
+pub fn read_tagfile_with(
+    file: &Path,
+    options: &TagfileOptions,
+) -> Result<Vec<TagOperation>, Error> {
+    let contents = read_tagfile_contents(file)?;
+    let mut operations = vec![];
+    for (i, line) in strip_bom(&contents).lines().enumerate() {
+        operations.extend(parse_tagfile_line_checked(file, i + 1, line, options)?);
+    }
+    Ok(operations)
+}
+
+pub(crate) fn strip_bom(contents: &str) -> &str {
+    contents.strip_prefix('\u{feff}').unwrap_or(contents)
+}
+
+/// Parses one line of a tag file. Returns None for blank and comment lines.
+pub(crate) fn parse_tagfile_line(line: &str) -> Option<TagOperation> {
+    parse_tagfile_line_with(line, &TagfileOptions::default())
+}
+
+/// The start of a tag file line that's a glob rather than a tag
+pub(crate) const GLOB_PREFIX: &str = "glob:";
+
+/// Like `parse_tagfile_line`, skipping lines that aren't valid with a warning
+pub(crate) fn parse_tagfile_line_with(
+    line: &str,
+    options: &TagfileOptions,
+) -> Option<TagOperation> {
+    try_parse_tagfile_line(line, options).unwrap_or_else(|reason| {
+        warn!("Skipping {}", reason);
+        None
+    })
+}
+
+/// Like `parse_tagfile_line_with`, failing with `Error::TagfileParseError` for lines that aren't valid.
+/// `number` is the line's 1-based line number in `file`.
+fn parse_tagfile_line_checked(
+    file: &Path,
+    number: usize,
+    line: &str,
+    options: &TagfileOptions,
+) -> Result<Option<TagOperation>, Error> {
+    try_parse_tagfile_line(line, options).map_err(|_| Error::TagfileParseError {
+        file: file.to_path_buf(),
+        line: number,
+        content: line.to_string(),
+    })
+}
+
+/// Checks whether a tag from a tag file has a name once empty `/` segments are dropped, and has no control
+/// characters
+pub(crate) fn is_valid_tag(tag: &str) -> bool {
+    tag.split('/').any(|segment| !segment.is_empty()) && !tag.contains(char::is_control)
+}
+
+/// Parses one line of a tag file, failing with the reason if it's an invalid glob or tag
+fn try_parse_tagfile_line(
+    line: &str,
+    options: &TagfileOptions,
+) -> Result<Option<TagOperation>, String> {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let tag = if options.trim_whitespace {
+        line.trim()
+    } else {
+        line
+    };
+    if tag.trim().is_empty() || tag.starts_with('#') || include_directive(tag).is_some() {
+        return Ok(None);
+    }
+    let operation = if let Some(escaped) = tag
+        .strip_prefix('\\')
+        .filter(|t| t.starts_with(['-', '!', '#', '@']) || t.starts_with(GLOB_PREFIX))
+    {
+        TagOperation::Add(escaped.to_string())
+    } else if let Some(glob) = tag.strip_prefix(GLOB_PREFIX) {
+        let pattern =
+            Pattern::new(glob.trim()).map_err(|e| format!("invalid glob {:?}: {}", glob, e))?;
+        TagOperation::Glob(pattern)
+    } else if let Some(removed) = tag.strip_prefix(['-', '!']) {
+        let removed = removed.trim_start();
+        if removed.is_empty() {
+            return Ok(None);
+        }
+        TagOperation::Remove(removed.to_string())
+    } else {
+        TagOperation::Add(tag.to_string())
+    };
+    match &operation {
+        TagOperation::Add(tag) | TagOperation::Remove(tag) if !is_valid_tag(tag) => {
+            Err(format!("invalid tag {:?}", tag))
+        }
+        _ => Ok(Some(operation)),
+    }
+}
+
+/// The name of the tag file whose lines tag every file in its directory matching a glob, with the default tag
+/// file extension. It follows `ScanOptions::tagfile_extension`. See `read_patterns_tagfile`.
+pub const PATTERNS_TAGFILE: &str = "_patterns.tags";
+
+/// Reads a patterns tag file, where each line is a glob and the tags for the files it matches, separated by
+/// commas: `*.png: screenshot, -wip`. Globs are matched against file names in the tag file's directory.
+/// Blank lines and `#` comments are skipped, and so are lines without a valid glob, with a warning.
+pub fn read_patterns_tagfile(file: &Path) -> Result<Vec<(Pattern, Vec<TagOperation>)>, Error> {
+    read_patterns(file, &mut vec![])
+}
+
+fn read_patterns(
+    file: &Path,
+    warnings: &mut Vec<ScanWarning>,
+) -> Result<Vec<(Pattern, Vec<TagOperation>)>, Error> {
+    let contents = read_tagfile_contents(file)?;
+    let mut patterns = vec![];
+    for (i, line) in strip_bom(&contents).lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((pattern, tags)) = line.split_once(':') else {
+            warn!("Line {:?} in {:?} has no pattern", line, file);
+            continue;
+        };
+        let pattern = match Pattern::new(pattern.trim()) {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                warn!(
+                    "Line {:?} in {:?} has an invalid pattern: {}",
+                    line, file, e
+                );
+                continue;
+            }
+        };
+        let operations = parse_tag_list(file, i + 1, tags, warnings);
+        patterns.push((pattern, operations));
+    }
+    Ok(patterns)
+}
+
+/// The name of the tag file listing the tags of files in its directory, one file per line, with the default tag
+/// file extension. It follows `ScanOptions::tagfile_extension`. See `read_manifest_tagfile`.
+pub const MANIFEST_TAGFILE: &str = "manifest.tags.tsv";
+
+/// Reads a manifest, where each line is a file name and its tags separated by a tab, with the tags
+/// separated by commas: `photo.jpg<TAB>beach, rating:4`. A file listed on several lines gets the tags
+/// from all of them. Blank lines and `#` comments are skipped, and so are lines without a tab, with a warning.
+pub fn read_manifest_tagfile(file: &Path) -> Result<Vec<(String, Vec<TagOperation>)>, Error> {
+    read_manifest(file, &mut vec![])
+}
+
+fn read_manifest(
+    file: &Path,
+    warnings: &mut Vec<ScanWarning>,
+) -> Result<Vec<(String, Vec<TagOperation>)>, Error> {
+    let contents = read_tagfile_contents(file)?;
+    let mut entries = vec![];
+    for (i, line) in strip_bom(&contents).lines().enumerate() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let Some((file_name, tags)) = line.split_once('\t') else {
+            warn!(
+                "Line {:?} in {:?} has no tab after the file name",
+                line, file
+            );
+            continue;
+        };
+        let operations = parse_tag_list(file, i + 1, tags, warnings);
+        entries.push((file_name.trim().to_string(), operations));
+    }
+    Ok(entries)
+}
+
+/// Parses the comma separated tags on line `number` of a patterns tag file or manifest.
+/// Invalid tags, and empty ones other than after a trailing comma, are skipped with a warning.
+fn parse_tag_list(
+    file: &Path,
+    number: usize,
+    tags: &str,
+    warnings: &mut Vec<ScanWarning>,
+) -> Vec<TagOperation> {
+    let segments: Vec<&str> = tags.split(',').collect();
+    let mut operations = vec![];
+    for (i, segment) in segments.iter().enumerate() {
+        let skipped = match try_parse_tagfile_line(segment, &TagfileOptions::default()) {
+            Ok(Some(operation)) => {
+                operations.push(operation);
+                continue;
+            }
+            Ok(None) if segment.trim().is_empty() && i > 0 && i + 1 < segments.len() => {
+                "an empty tag".to_string()
+            }
+            Ok(None) => continue,
+            Err(reason) => reason,
+        };
+        warn!("Skipping {} on line {} of {:?}", skipped, number, file);
+        warnings.push(ScanWarning::EmptyTag {
+            tagfile: file.to_path_buf(),
+            line: number,
+        });
+    }
+    operations
+}
+
+/// The name of the file listing globs of paths to leave out of a scan, like a `.gitignore`.
+/// See `parse_tagsignore`.
+pub const TAGSIGNORE_FILE: &str = ".tagsignore";
+
+/// Reads the `.tagsignore` in a directory, with a glob on each line. The globs apply to everything under
+/// the directory: ones without a `/` are matched against names, and the rest against paths relative to the
+/// directory. Blank lines and `#` comments are skipped, and so are invalid globs, with a warning.
+/// A directory without a `.tagsignore` has no globs.
+pub fn parse_tagsignore(dir: &Path) -> Vec<Pattern> {
+    let file = dir.join(TAGSIGNORE_FILE);
+    let contents = match fs::read_to_string(&file) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return vec![],
+        Err(e) => {
+            warn!("Couldn't read {:?}: {}", file, e);
+            return vec![];
+        }
+    };
+    strip_bom(&contents)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| match Pattern::new(line.trim_end_matches('/')) {
+            Ok(pattern) => Some(pattern),
+            Err(e) => {
+                warn!(
+                    "Line {:?} in {:?} has an invalid pattern: {}",
+                    line, file, e
+                );
+                None
+            }
+        })
+        .collect()
 }
 
-pub fn get_tagged_files(
-    root: &str,
-) -> Result<HashSetGraph<TagGraphNode, Relation, Directed>, Error> {
-    let mut tag_graph = HashSetGraph::<TagGraphNode, Relation, Directed>::new();
+/// The `.tagsignore` globs that apply while walking a root, read as their directories are reached
+struct TagsIgnore {
+    root: PathBuf,
+    patterns: Mutex<HashMap<PathBuf, Arc<Vec<Pattern>>>>,
+}
+
+impl TagsIgnore {
+    fn new(root: &Path) -> Self {
+        TagsIgnore {
+            root: root.to_path_buf(),
+            patterns: Mutex::new(HashMap::new()),
+        }
+    }
 
-    add_tags_to_graph(root, &mut tag_graph)?;
-    add_file_structure_to_graph(root, &mut tag_graph)?;
+    /// Checks whether a walked path matches the `.tagsignore` of any directory above it, up to the root
+    fn is_ignored(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name() else {
+            return false;
+        };
+        for dir in path.ancestors().skip(1) {
+            let Ok(relative) = path.strip_prefix(dir) else {
+                break;
+            };
+            let patterns = self.patterns_in(dir);
+            let matched = patterns.iter().any(|pattern| {
+                if pattern.as_str().contains('/') {
+                    pattern.matches_path(relative)
+                } else {
+                    pattern.matches_path(Path::new(name))
+                }
+            });
+            if matched {
+                return true;
+            }
+            if dir == self.root {
+                break;
+            }
+        }
+        false
+    }
 
-    Ok(tag_graph)
+    fn patterns_in(&self, dir: &Path) -> Arc<Vec<Pattern>> {
+        let mut patterns = self.patterns.lock().unwrap_or_else(|e| e.into_inner());
+        patterns
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| Arc::new(parse_tagsignore(dir)))
+            .clone()
+    }
 }
 
-fn add_tags_to_graph(
-    root: &str,
-    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+/// Gets the path from an `@include <path>` line
+pub(crate) fn include_directive(line: &str) -> Option<&str> {
+    line.trim()
+        .strip_prefix("@include")
+        .filter(|rest| rest.starts_with(char::is_whitespace))
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+}
+
+/// Reads a tag file like `read_tagfile`, following `@include <path>` lines.
+/// Included paths are relative to the file including them, and their tags take the place of the `@include`
+/// line. Fails with `Error::TagfileInclude` if an included file can't be found. A file that's already being
+/// included further up is skipped, so include cycles don't recurse forever.
+pub fn read_tagfile_with_includes(file: &Path) -> Result<Vec<TagOperation>, Error> {
+    let mut operations = vec![];
+    let mut including = vec![canonicalize(file)?];
+    read_includes(file, &mut including, &mut operations)?;
+    Ok(operations)
+}
+
+fn read_includes(
+    file: &Path,
+    including: &mut Vec<PathBuf>,
+    operations: &mut Vec<TagOperation>,
 ) -> Result<(), Error> {
-    let tag_root = tag_graph.get_node(&TagGraphNode::RootTag);
-    let pattern = format!("{}/**/*.tags", root);
-    trace!("Searching for tag files using {}", &pattern);
-    for tagfile in glob(&pattern).expect("Failed to read glob pattern") {
-        match tagfile {
-            Ok(tagfile) => {
-                trace!("Visiting tagfile {}", tagfile.as_path().to_string_lossy());
-                let mut dirpath = tagfile.as_path().canonicalize()?;
-                dirpath.pop();
-                let dir = tag_graph.get_node_move(TagGraphNode::Directory {
-                    path: dirpath.clone(),
-                });
-                match tagfile.file_name() {
-                    Some(name) => {
-                        // Collect the tag attach targets
-                        let mut tag_attach_targets: Vec<NodeIndex> = vec![];
-                        if name == "dir.tags" {
-                            trace!("This is a directory tagfile. attach target: {:?}", dir);
-                            tag_attach_targets.push(dir);
-                        } else {
-                            // Files with the matching name
-                            let tagfile_stem = tagfile.file_stem().unwrap();
-                            let mut found = false;
-                            for path in fs::read_dir(dirpath)? {
-                                if let Ok(path) = path {
-                                    let file_path = path.path();
-                                    if let Some(ext) = file_path.extension() {
-                                        // Don't associate a tagfile with itself
-                                        if ext == "tags" {
-                                            continue;
-                                        }
-                                    }
-                                    let file_stem = file_path.file_stem().unwrap();
-                                    let file_name = file_path.file_name().unwrap();
-                                    if file_stem == tagfile_stem || file_name == tagfile_stem {
-                                        found = true;
-                                        trace!("Found file {}", file_path.to_string_lossy());
-                                        let t = tag_graph
-                                            .get_node_move(TagGraphNode::File { path: file_path });
-                                        trace!("   ... assigned it {:?}", t);
-                                        tag_attach_targets.push(t);
-                                    }
-                                }
-                            }
-                            if !found {
-                                warn!("Tag file {:?} has no associated files", tagfile)
-                            }
-                        }
+    let contents = read_tagfile_contents(file)?;
+    for (i, line) in strip_bom(&contents).lines().enumerate() {
+        let Some(include) = include_directive(line) else {
+            operations.extend(parse_tagfile_line_checked(
+                file,
+                i + 1,
+                line,
+                &TagfileOptions::default(),
+            )?);
+            continue;
+        };
 
-                        // Attach the tags to the targets
-                        for tag in read_tagfile(&tagfile)? {
-                            trace!("Tagfile contains tag {}", tag);
-                            let t = tag_graph.get_node_move(TagGraphNode::Tag(tag.clone()));
-                            tag_graph.graph.update_edge(tag_root, t, Relation::HasTag);
-                            tag_graph.graph.update_edge(tag_root, t, Relation::HasTag);
-                            for attach_target in &tag_attach_targets {
-                                trace!("Attaching tag {:?} to {:?}", t, attach_target);
-                                tag_graph
-                                    .graph
-                                    .update_edge(*attach_target, t, Relation::HasTag);
-                                tag_graph
-                                    .graph
-                                    .update_edge(t, *attach_target, Relation::TagAssignedTo);
-                            }
-                        }
-                    }
-                    None => (),
+        let path = file.parent().unwrap_or(Path::new("")).join(include);
+        let canonical = path
+            .canonicalize()
+            .map_err(|source| Error::TagfileInclude {
+                path: path.clone(),
+                source,
+            })?;
+        if including.contains(&canonical) {
+            warn!("Tag file {:?} includes itself through {:?}", file, path);
+            continue;
+        }
+        including.push(canonical);
+        read_includes(&path, including, operations)?;
+        including.pop();
+    }
+    Ok(())
+}
+
+/// Gets the paths of every file and directory a tag is assigned to, in sorted order.
+/// If `include_contained` is set, everything inside a tagged directory is included as well.
+/// Unknown tags produce an empty list.
+pub fn files_with_tag(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    tag: &str,
+    include_contained: bool,
+) -> Vec<PathBuf> {
+    let mut paths = BTreeSet::new();
+    let mut visited = HashSet::new();
+    let mut to_visit: Vec<(NodeIndex, bool)> = tag_assignments(tag_graph, tag, false)
+        .into_iter()
+        .map(|node| (node, true))
+        .collect();
+
+    while let Some((node, assigned)) = to_visit.pop() {
+        let path = match &tag_graph.graph[node] {
+            TagGraphNode::File { path }
+            | TagGraphNode::Directory { path }
+            | TagGraphNode::Symlink { path, .. } => path,
+            _ => continue,
+        };
+        if !visited.insert(node) {
+            continue;
+        }
+        // Contained paths may have opted out of the tag
+        if assigned || node_tags(tag_graph, node, true).contains(tag) {
+            paths.insert(path.clone());
+        }
+        if include_contained {
+            to_visit.extend(
+                tag_graph
+                    .graph
+                    .edges(node)
+                    .filter(|e| *e.weight() == Relation::Child)
+                    .map(|e| (e.target(), false)),
+            );
+        }
+    }
+
+    paths.into_iter().collect()
+}
+
+/// Gets the paths of every file a tag is assigned to, including through its directories, in sorted order.
+pub fn get_files_with_tag(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    tag: &str,
+) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = tag_assignments(tag_graph, tag, true)
+        .into_iter()
+        .filter_map(|node| match &tag_graph.graph[node] {
+            TagGraphNode::File { path } => Some(path.clone()),
+            _ => None,
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Gets the paths of every file with a tag or any tag in its namespace, in sorted order. `lang` finds files
+/// tagged `lang`, `lang:rust` and `lang:python`. Tags from a file's directories count, like in `get_files_with_tag`.
+pub fn get_files_with_tag_prefix(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    prefix: &str,
+) -> Vec<PathBuf> {
+    let prefix = prefix.strip_suffix(':').unwrap_or(prefix);
+    let mut tags = vec![prefix.to_string()];
+    if let Some(&ns) = tag_graph
+        .map
+        .get(&TagGraphNode::TagNamespace(prefix.to_string()))
+    {
+        tags.extend(
+            tag_graph
+                .graph
+                .edges(ns)
+                .filter(|e| *e.weight() == Relation::NamespaceValue)
+                .filter_map(|e| match &tag_graph.graph[e.target()] {
+                    TagGraphNode::Tag(tag) => Some(tag.clone()),
+                    _ => None,
+                }),
+        );
+    }
+
+    let paths: BTreeSet<PathBuf> = tags
+        .iter()
+        .flat_map(|tag| get_files_with_tag(tag_graph, tag))
+        .collect();
+    paths.into_iter().collect()
+}
+
+/// Gets the nodes a tag, or any tag nested under it, is directly assigned to,
+/// and the nodes inheriting it if `inherited` is set. Unknown tags aren't assigned to anything.
+fn tag_assignments(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    tag: &str,
+    inherited: bool,
+) -> Vec<NodeIndex> {
+    let Some(&tag_node) = tag_graph.map.get(&TagGraphNode::Tag(tag.to_string())) else {
+        return vec![];
+    };
+
+    let mut assigned = BTreeSet::new();
+    let mut to_visit = vec![tag_node];
+    while let Some(tag_node) = to_visit.pop() {
+        for edge in tag_graph.graph.edges(tag_node) {
+            match edge.weight() {
+                Relation::TagAssignedTo => {
+                    assigned.insert(edge.target());
+                }
+                Relation::InheritedTagAssignedTo if inherited => {
+                    assigned.insert(edge.target());
                 }
+                Relation::ChildTag => to_visit.push(edge.target()),
+                _ => (),
             }
-            Err(_) => todo!(),
         }
     }
-    Ok(())
+    assigned.into_iter().collect()
+}
+
+/// Gets every tag that applies to a file or directory, including the ones inherited from its
+/// ancestor directories, in sorted order.
+pub fn get_tags_for_path(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    path: &Path,
+) -> Vec<String> {
+    match path_node(tag_graph, path) {
+        Some(node) => effective_tags(tag_graph, node),
+        None => vec![],
+    }
+}
+
+/// Like `get_tags_for_path`, but also counts the tags of the files `path` is an alias of, and of the files
+/// those are aliases of, and so on. See `add_alias`.
+pub fn get_tags_for_path_with_aliases(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    path: &Path,
+) -> Vec<String> {
+    let Some(node) = path_node(tag_graph, path) else {
+        return vec![];
+    };
+    let mut tags = BTreeSet::new();
+    let mut visited = HashSet::new();
+    let mut to_visit = vec![node];
+    while let Some(node) = to_visit.pop() {
+        // Guards against alias cycles
+        if !visited.insert(node) {
+            continue;
+        }
+        tags.extend(tag_sources(tag_graph, node, true).into_keys());
+        to_visit.extend(
+            tag_graph
+                .graph
+                .edges(node)
+                .filter(|e| *e.weight() == Relation::Alias)
+                .map(|e| e.target()),
+        );
+    }
+    tags.into_iter().map(str::to_string).collect()
 }
 
-fn add_file_structure_to_graph(
-    root: &str,
+/// Marks the file at `from` as a copy or alias of the file at `to`, like a `.lnk` pointing to the original.
+/// Both files need to be in the graph already. `get_tags_for_path_with_aliases` follows these.
+pub fn add_alias(
     tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+    from: &Path,
+    to: &Path,
 ) -> Result<(), Error> {
-    let dir_root = tag_graph.get_node(&TagGraphNode::RootDirectory);
-    for entry in WalkDir::new(root) {
-        match entry {
-            Ok(entry) => {
-                let path = entry.path().canonicalize().unwrap();
-                if let Some(extension) = path.extension() {
-                    if extension == "tags" {
-                        continue;
-                    }
-                }
+    let file_node = |path: &Path| match path_node(tag_graph, path) {
+        Some(node) if matches!(tag_graph.graph[node], TagGraphNode::File { .. }) => Ok(node),
+        _ => Err(Error::FileNotInGraph {
+            path: path.to_path_buf(),
+        }),
+    };
+    let (from, to) = (file_node(from)?, file_node(to)?);
+    tag_graph.graph.update_edge(from, to, Relation::Alias);
+    Ok(())
+}
 
-                let node = if path.is_dir() {
-                    tag_graph.get_node_move(TagGraphNode::Directory {
-                        path: path.to_path_buf(),
-                    })
-                } else {
-                    tag_graph.get_node_move(TagGraphNode::File {
-                        path: path.to_path_buf(),
-                    })
-                };
+/// Gets the tags that every one of the paths has, counting inherited ones like `get_tags_for_path`, in sorted
+/// order. No paths have no tags in common.
+pub fn get_common_tags(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    paths: &[&Path],
+) -> Vec<String> {
+    let Some((first, rest)) = paths.split_first() else {
+        return vec![];
+    };
+    let mut common = get_tags_for_path(tag_graph, first);
+    for path in rest {
+        if common.is_empty() {
+            break;
+        }
+        let tags: HashSet<String> = get_tags_for_path(tag_graph, path).into_iter().collect();
+        common.retain(|tag| tags.contains(tag));
+    }
+    common
+}
 
-                if entry.depth() == 0 {
-                    tag_graph.graph.update_edge(dir_root, node, Relation::Child);
-                    tag_graph
-                        .graph
-                        .update_edge(node, dir_root, Relation::Parent);
-                } else {
-                    let parent = tag_graph.get_node_move(TagGraphNode::Directory {
-                        path: path.parent().unwrap().canonicalize().unwrap().to_path_buf(),
-                    });
-                    tag_graph.graph.update_edge(parent, node, Relation::Child);
-                    tag_graph.graph.update_edge(node, parent, Relation::Parent);
+/// Gets the files sharing the most tags with a path, along with how many tags they share, most first and then
+/// by path. At most `limit` files are returned. Tags count whether they're assigned or inherited, so this
+/// needs the inherited edges from `inherit_tags`. Only the files that share at least one tag are looked at,
+/// by following each tag of `path` to what it's assigned to.
+pub fn get_related_files(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    path: &Path,
+    limit: usize,
+) -> Vec<(PathBuf, usize)> {
+    let Some(node) = path_node(tag_graph, path) else {
+        return vec![];
+    };
+    let tags: HashSet<NodeIndex> = tag_graph
+        .graph
+        .edges(node)
+        .filter(|e| matches!(e.weight(), Relation::HasTag | Relation::InheritedTag))
+        .map(|e| e.target())
+        .collect();
+
+    let mut shared: HashMap<NodeIndex, usize> = HashMap::new();
+    for tag in tags {
+        let assigned: HashSet<NodeIndex> = tag_graph
+            .graph
+            .edges(tag)
+            .filter(|e| {
+                matches!(
+                    e.weight(),
+                    Relation::TagAssignedTo | Relation::InheritedTagAssignedTo
+                )
+            })
+            .map(|e| e.target())
+            .filter(|&other| other != node)
+            .collect();
+        for other in assigned {
+            *shared.entry(other).or_default() += 1;
+        }
+    }
+
+    let mut related: Vec<(PathBuf, usize)> = shared
+        .into_iter()
+        .filter_map(|(other, count)| match &tag_graph.graph[other] {
+            TagGraphNode::File { path } => Some((path.clone(), count)),
+            _ => None,
+        })
+        .collect();
+    related.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+    related.truncate(limit);
+    related
+}
+
+/// Gets every tag that applies to a node: its own tags, plus the tags of every directory above it.
+/// The result is sorted and has no duplicates.
+pub fn effective_tags(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    node: NodeIndex,
+) -> Vec<String> {
+    let mut tags: Vec<String> = tag_sources(tag_graph, node, true)
+        .into_keys()
+        .map(str::to_string)
+        .collect();
+    tags.sort();
+    tags
+}
+
+/// Like `effective_tags`, but pairs each tag with the node it was inherited from
+/// (the node itself for its own tags). If several ancestors carry a tag, the closest one is reported.
+pub fn effective_tags_with_sources(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    node: NodeIndex,
+) -> Vec<(String, NodeIndex)> {
+    let mut tags: Vec<(String, NodeIndex)> = tag_sources(tag_graph, node, true)
+        .into_iter()
+        .map(|(tag, source)| (tag.to_string(), source))
+        .collect();
+    tags.sort();
+    tags
+}
+
+/// Finds the node of a file or directory. Paths that aren't canonical are canonicalized first.
+pub(crate) fn path_node(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    path: &Path,
+) -> Option<NodeIndex> {
+    let find = |path: &Path| {
+        let path = path.to_path_buf();
+        let symlink = || {
+            let target = fs::read_link(&path).ok()?;
+            tag_graph.map.get(&TagGraphNode::Symlink {
+                path: path.clone(),
+                target,
+            })
+        };
+        tag_graph
+            .map
+            .get(&TagGraphNode::File { path: path.clone() })
+            .or_else(|| {
+                tag_graph
+                    .map
+                    .get(&TagGraphNode::Directory { path: path.clone() })
+            })
+            .or_else(symlink)
+            .copied()
+    };
+    find(path).or_else(|| find(&path.canonicalize().ok()?))
+}
+
+/// Gets the paths of every file carrying the `namespace:value` tag, including through its directories, in sorted order.
+pub fn files_with_attr(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    namespace: &str,
+    value: &str,
+) -> Vec<PathBuf> {
+    // Tags are looked up rather than formatted, since their colons may be written escaped
+    let paths: BTreeSet<PathBuf> = namespace_values(tag_graph, namespace)
+        .into_iter()
+        .filter(|(v, _)| v == value)
+        .flat_map(|(_, tag)| {
+            query_files(
+                tag_graph,
+                &QuerySpec {
+                    all: &[&tag],
+                    include_directory_tags: true,
+                    ..Default::default()
+                },
+            )
+        })
+        .collect();
+    paths.into_iter().collect()
+}
+
+/// Gets the name of every tag in the graph, in sorted order.
+pub fn get_all_tags(tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>) -> Vec<String> {
+    let Some(&tag_root) = tag_graph.map.get(&TagGraphNode::RootTag) else {
+        return vec![];
+    };
+    let mut tags: Vec<String> = tag_graph
+        .graph
+        .edges(tag_root)
+        .filter(|e| *e.weight() == Relation::HasTag)
+        .filter_map(|e| match &tag_graph.graph[e.target()] {
+            TagGraphNode::Tag(tag) => Some(tag.clone()),
+            _ => None,
+        })
+        .collect();
+    tags.sort();
+    tags
+}
+
+/// How much is in a tag graph, from `compute_stats`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TagStats {
+    /// How many files and symbolic links are in the graph
+    pub file_count: usize,
+    pub dir_count: usize,
+    pub tag_count: usize,
+    /// Each tag and how many files have it, including through their directories, most used first
+    pub tags_by_frequency: Vec<(String, usize)>,
+    /// Each file and how many tags it has, including inherited ones, most tagged first
+    pub files_by_tag_count: Vec<(PathBuf, usize)>,
+    /// The tags nothing has, in sorted order
+    pub unassigned_tags: Vec<String>,
+}
+
+/// Counts the files, directories and tags in a graph, and works out which tags and files are used the most
+pub fn compute_stats(tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>) -> TagStats {
+    let mut stats = TagStats::default();
+    for idx in tag_graph.graph.node_indices() {
+        match &tag_graph.graph[idx] {
+            TagGraphNode::File { path } => {
+                stats.file_count += 1;
+                stats
+                    .files_by_tag_count
+                    .push((path.clone(), effective_tags(tag_graph, idx).len()));
+            }
+            TagGraphNode::Symlink { .. } => stats.file_count += 1,
+            TagGraphNode::Directory { .. } => stats.dir_count += 1,
+            TagGraphNode::Tag(tag) => {
+                stats.tag_count += 1;
+                if tag_assignments(tag_graph, tag, true).is_empty() {
+                    stats.unassigned_tags.push(tag.clone());
                 }
+                stats
+                    .tags_by_frequency
+                    .push((tag.clone(), get_files_with_tag(tag_graph, tag).len()));
             }
-            Err(e) => {
-                error!("Error when walking file structure: {:?}", e);
+            _ => (),
+        }
+    }
+
+    stats
+        .tags_by_frequency
+        .sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+    stats
+        .files_by_tag_count
+        .sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+    stats.unassigned_tags.sort();
+    stats
+}
+
+/// Gets the paths of every file without a tag, in sorted order. With `include_inherited`, files with a tag from
+/// one of their directories count as tagged too, so only the effectively untagged ones are left. Virtual tags,
+/// like the ones from `add_virtual_tags_by_extension`, don't count.
+pub fn untagged_files(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    include_inherited: bool,
+) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = tag_graph
+        .graph
+        .node_indices()
+        .filter_map(|idx| match &tag_graph.graph[idx] {
+            TagGraphNode::File { path } if !is_tagged(tag_graph, idx, include_inherited) => {
+                Some(path.clone())
             }
+            _ => None,
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Counts the files `untagged_files` finds in each directory, not including subdirectories. Directories with
+/// the most untagged files come first, and directories without any are left out.
+pub fn untagged_files_by_directory(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    include_inherited: bool,
+) -> Vec<(PathBuf, usize)> {
+    let mut counts: HashMap<PathBuf, usize> = HashMap::new();
+    for path in untagged_files(tag_graph, include_inherited) {
+        if let Some(dir) = path.parent() {
+            *counts.entry(dir.to_path_buf()).or_default() += 1;
         }
     }
+    let mut counts: Vec<(PathBuf, usize)> = counts.into_iter().collect();
+    counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+    counts
+}
 
-    Ok(())
+/// Checks whether a node has a tag that isn't virtual, optionally counting the ones from its directories
+fn is_tagged(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    node: NodeIndex,
+    include_inherited: bool,
+) -> bool {
+    tag_sources(tag_graph, node, include_inherited)
+        .into_keys()
+        .any(|tag| tag_graph.contains_node(&TagGraphNode::Tag(tag.to_string())))
 }
 
-/// Reads a tag file
-/// A tag file is simply a text file where each line is a tag
-pub fn read_tagfile(file: &PathBuf) -> Result<Vec<String>, Error> {
-    let file = File::open(file)?;
-    let mut tags = vec![];
-    for line in io::BufReader::new(file).lines() {
-        tags.push(line?);
+/// Gets every value used with a namespace, e.g. `5` for `rating:5`, in sorted order.
+pub fn values_of(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    namespace: &str,
+) -> Vec<String> {
+    let mut values: Vec<String> = namespace_values(tag_graph, namespace)
+        .into_iter()
+        .map(|(value, _)| value)
+        .collect();
+    values.sort();
+    values.dedup();
+    values
+}
+
+/// Gets the `(value, tag)` pairs of a namespace, with each value unescaped and each tag's name as written
+fn namespace_values(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    namespace: &str,
+) -> Vec<(String, String)> {
+    let Some(&ns) = tag_graph
+        .map
+        .get(&TagGraphNode::TagNamespace(namespace.to_string()))
+    else {
+        return vec![];
+    };
+    tag_graph
+        .graph
+        .edges(ns)
+        .filter(|e| *e.weight() == Relation::NamespaceValue)
+        .filter_map(|e| match &tag_graph.graph[e.target()] {
+            TagGraphNode::Tag(tag) => Some((split_namespace(tag)?.1, tag.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Describes which tags a file must (`all`), may (`any`) and must not (`none`) have.
+#[derive(Debug, Default, Clone)]
+pub struct QuerySpec<'a> {
+    pub all: &'a [&'a str],
+    pub any: &'a [&'a str],
+    pub none: &'a [&'a str],
+    /// Whether tags assigned to a directory (and its ancestors) count for the files inside it
+    pub include_directory_tags: bool,
+}
+
+/// Gets the paths of every tagged file matching a query, in sorted order.
+/// An empty `all` and `any` matches every tagged file that isn't excluded by `none`.
+pub fn query_files(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    spec: &QuerySpec,
+) -> Vec<PathBuf> {
+    let mut paths = BTreeSet::new();
+    for node in tag_graph.graph.node_indices() {
+        let TagGraphNode::File { path } = &tag_graph.graph[node] else {
+            continue;
+        };
+        let tags = node_tags(tag_graph, node, spec.include_directory_tags);
+        if tags.is_empty() {
+            continue;
+        }
+        if spec.all.iter().all(|t| tags.contains(t))
+            && (spec.any.is_empty() || spec.any.iter().any(|t| tags.contains(t)))
+            && !spec.none.iter().any(|t| tags.contains(t))
+        {
+            paths.insert(path.clone());
+        }
+    }
+    paths.into_iter().collect()
+}
+
+/// Gets the shortest path between two nodes following edge directions, starting with `from` and ending
+/// with `to`. Every edge counts the same. Returns `None` if either node isn't in the graph or `to` can't
+/// be reached.
+pub fn find_path(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    from: &TagGraphNode,
+    to: &TagGraphNode,
+) -> Option<Vec<TagGraphNode>> {
+    let start = *tag_graph.map.get(from)?;
+    let goal = *tag_graph.map.get(to)?;
+    // A* without a heuristic is Dijkstra, and unlike `algo::dijkstra` it gives back the path
+    let (_, path) = astar(&tag_graph.graph, start, |n| n == goal, |_| 1, |_| 0)?;
+    Some(
+        path.into_iter()
+            .map(|n| tag_graph.graph[n].clone())
+            .collect(),
+    )
+}
+
+/// Gets a short label for a node, like `photo.jpg`, `photos/` or `[beach]`
+pub fn node_label(node: &TagGraphNode) -> String {
+    let name = |path: &Path| {
+        path.file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy()
+            .to_string()
+    };
+    match node {
+        TagGraphNode::File { path } => name(path),
+        TagGraphNode::Directory { path } => format!("{}/", name(path)),
+        TagGraphNode::Symlink { path, target } => {
+            format!("{} -> {}", name(path), target.to_string_lossy())
+        }
+        TagGraphNode::RootDirectory => "ROOT_DIR".to_string(),
+        TagGraphNode::RootTag => "ROOT_TAG".to_string(),
+        TagGraphNode::Tag(t) => format!("[{}]", t),
+        TagGraphNode::TagNamespace(ns) => format!("[{}:]", ns),
+        TagGraphNode::VirtualTag { name, source } => format!("[{}] ({})", name, source),
+    }
+}
+
+/// Gets the name of a node's kind, like `file` or `tag`, as used by `to_json` and the exports
+pub(crate) fn node_kind(node: &TagGraphNode) -> &'static str {
+    match node {
+        TagGraphNode::File { .. } => "file",
+        TagGraphNode::Directory { .. } => "directory",
+        TagGraphNode::Symlink { .. } => "symlink",
+        TagGraphNode::RootDirectory => "root_directory",
+        TagGraphNode::RootTag => "root_tag",
+        TagGraphNode::Tag(_) => "tag",
+        TagGraphNode::TagNamespace(_) => "tag_namespace",
+        TagGraphNode::VirtualTag { .. } => "virtual_tag",
+    }
+}
+
+/// Collects the tags assigned to a node along with their parent tags,
+/// including the ones on its ancestor directories if `inherit` is set.
+pub(crate) fn node_tags(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    node: NodeIndex,
+    inherit: bool,
+) -> HashSet<&str> {
+    tag_sources(tag_graph, node, inherit).into_keys().collect()
+}
+
+/// Like `node_tags`, but also records which node each tag was assigned to.
+/// When several nodes contribute the same tag, the closest one wins.
+/// Tags excluded by a node aren't inherited from any node further away.
+fn tag_sources(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    node: NodeIndex,
+    inherit: bool,
+) -> HashMap<&str, NodeIndex> {
+    let mut sources = HashMap::new();
+    let mut excluded = HashSet::new();
+    let mut visited = HashSet::new();
+    // Breadth first, so nearer ancestors are seen first
+    let mut to_visit = VecDeque::from([node]);
+    while let Some(current) = to_visit.pop_front() {
+        // Guards against directory cycles
+        if !visited.insert(current) {
+            continue;
+        }
+        for edge in tag_graph.graph.edges(current) {
+            if *edge.weight() == Relation::ExcludesTag {
+                excluded.insert(edge.target());
+            }
+        }
+        for edge in tag_graph.graph.edges(current) {
+            match edge.weight() {
+                Relation::HasTag => {
+                    // The tag and everything above it in the tag hierarchy
+                    let chain = tag_ancestry(tag_graph, edge.target());
+                    if chain.iter().any(|t| excluded.contains(t)) {
+                        continue;
+                    }
+                    for t in chain {
+                        if let TagGraphNode::Tag(name) | TagGraphNode::VirtualTag { name, .. } =
+                            &tag_graph.graph[t]
+                        {
+                            sources.entry(name.as_str()).or_insert(current);
+                        }
+                    }
+                }
+                Relation::Parent if inherit => to_visit.push_back(edge.target()),
+                _ => (),
+            }
+        }
+    }
+    sources
+}
+
+/// Gets a tag followed by each of its parent tags, nearest first
+fn tag_ancestry(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    tag: NodeIndex,
+) -> Vec<NodeIndex> {
+    let mut chain = vec![tag];
+    while let Some(parent) = tag_graph
+        .graph
+        .edges(chain[chain.len() - 1])
+        .find(|e| *e.weight() == Relation::ParentTag)
+        .map(|e| e.target())
+    {
+        chain.push(parent);
     }
-    Ok(tags)
+    chain
 }
 
-pub struct HashSetGraph<N, E, Ty>
+/// A graph where each node weight appears only once, so nodes can be looked up by their weight.
+/// With the `serde` feature, it's serialized as a list of node weights and a list of `(source, target, weight)`
+/// edges referring to positions in it. The weight to index map is rebuilt when deserializing, so `get_node`
+/// finds the nodes that were already there.
+pub struct HashSetGraph<N, E, Ty = Directed>
 where
     Ty: petgraph::EdgeType,
     N: Eq + std::hash::Hash + Clone,
@@ -188,11 +2816,18 @@ where
     map: HashMap<N, NodeIndex>,
 }
 
+/// A `HashSetGraph` with directed edges, like the tag graphs built by `get_tagged_files`
+pub type DirectedHashSetGraph<N, E> = HashSetGraph<N, E, Directed>;
+
+/// A `HashSetGraph` with undirected edges
+pub type UndirectedHashSetGraph<N, E> = HashSetGraph<N, E, petgraph::Undirected>;
+
 impl<N, E, Ty> HashSetGraph<N, E, Ty>
 where
     Ty: petgraph::EdgeType,
     N: Eq + std::hash::Hash + Clone,
 {
+    #[must_use]
     pub fn new() -> Self {
         Self {
             graph: StableGraph::default(),
@@ -200,7 +2835,22 @@ where
         }
     }
 
+    /// Wraps an existing graph, so its nodes can be looked up by weight.
+    /// Fails with `Error::DuplicateNode` if two nodes have the same weight.
+    pub fn from_petgraph(graph: StableGraph<N, E, Ty>) -> Result<Self, Error> {
+        let mut map = HashMap::new();
+        for idx in graph.node_indices() {
+            let weight = &graph[idx];
+            if let Some(&first) = map.get(weight) {
+                return Err(Error::DuplicateNode { first, second: idx });
+            }
+            map.insert(weight.clone(), idx);
+        }
+        Ok(Self { graph, map })
+    }
+
     /// Gets the index of a node. Adds it to the graph if it didn't already exist.
+    #[must_use]
     pub fn get_node(&mut self, weight: &N) -> NodeIndex {
         if let Some(existing) = self.map.get(weight) {
             return *existing;
@@ -212,34 +2862,443 @@ where
     }
 
     /// Gets the index of a node. Adds it to the graph if it didn't already exist.
+    #[must_use]
     pub fn get_node_move(&mut self, weight: N) -> NodeIndex {
         if let Some(existing) = self.map.get(&weight) {
             return *existing;
         }
 
         let idx = self.graph.add_node(weight.clone());
-        self.map.insert(weight.clone(), idx);
+        self.map.insert(weight, idx);
         idx
     }
 
     /// Updates an edge between two nodes. The nodes are created if they didn't exist.
     pub fn update_edge(&mut self, a: &N, b: &N, weight: E) {
-        let ax = self.get_node(&a);
-        let bx = self.get_node(&b);
+        let ax = self.get_node(a);
+        let bx = self.get_node(b);
         self.graph.update_edge(ax, bx, weight);
     }
+
+    #[must_use]
+    pub fn node_count(&self) -> usize {
+        self.graph.node_count()
+    }
+
+    #[must_use]
+    pub fn edge_count(&self) -> usize {
+        self.graph.edge_count()
+    }
+
+    /// Iterates over the weights of every node.
+    pub fn iter_nodes(&self) -> impl Iterator<Item = &N> {
+        self.graph.node_weights()
+    }
+
+    /// Iterates over every edge as (source weight, target weight, edge weight).
+    pub fn iter_edges(&self) -> impl Iterator<Item = (&N, &N, &E)> {
+        self.graph
+            .edge_references()
+            .map(|e| (&self.graph[e.source()], &self.graph[e.target()], e.weight()))
+    }
+
+    /// Gets the weight of the node at an index.
+    #[must_use]
+    pub fn weight_of(&self, idx: NodeIndex) -> Option<&N> {
+        self.graph.node_weight(idx)
+    }
+
+    /// Gets the weight stored in the graph that equals the given one, without adding it.
+    #[must_use]
+    pub fn get_node_weight(&self, weight: &N) -> Option<&N> {
+        self.map
+            .get(weight)
+            .and_then(|idx| self.graph.node_weight(*idx))
+    }
+
+    /// Checks whether a node is in the graph, without adding it.
+    #[must_use]
+    pub fn contains_node(&self, weight: &N) -> bool {
+        self.map.contains_key(weight)
+    }
+
+    /// Checks whether there is an edge from a to b, without adding either node.
+    #[must_use]
+    pub fn contains_edge(&self, a: &N, b: &N) -> bool {
+        match (self.map.get(a), self.map.get(b)) {
+            (Some(&ax), Some(&bx)) => self.graph.contains_edge(ax, bx),
+            _ => false,
+        }
+    }
+
+    /// Gets the weight of an edge from a to b. If there are several, any one of them is returned.
+    #[must_use]
+    pub fn edge_weight(&self, a: &N, b: &N) -> Option<&E> {
+        let (&ax, &bx) = (self.map.get(a)?, self.map.get(b)?);
+        self.graph
+            .find_edge(ax, bx)
+            .and_then(|e| self.graph.edge_weight(e))
+    }
+
+    /// Iterates over the weights of every edge from a to b. For undirected graphs, edges from b to a count too.
+    pub fn edges_between<'a>(&'a self, a: &N, b: &N) -> impl Iterator<Item = &'a E> {
+        let ends = match (self.map.get(a), self.map.get(b)) {
+            (Some(&ax), Some(&bx)) => Some((ax, bx)),
+            _ => None,
+        };
+        ends.into_iter()
+            .flat_map(move |(ax, bx)| self.graph.edges_connecting(ax, bx))
+            .map(|e| e.weight())
+    }
+
+    /// Iterates over the weights of a node's neighbors. For directed graphs, these are the targets of its
+    /// outgoing edges. A node that isn't in the graph has no neighbors.
+    pub fn neighbors<'a>(&'a self, weight: &N) -> impl Iterator<Item = &'a N> {
+        self.map
+            .get(weight)
+            .into_iter()
+            .flat_map(move |&idx| self.graph.neighbors(idx))
+            .map(move |idx| &self.graph[idx])
+    }
+
+    /// Iterates over the weights of a node's neighbors in one direction, like `neighbors`.
+    pub fn neighbors_directed<'a>(
+        &'a self,
+        weight: &N,
+        dir: petgraph::Direction,
+    ) -> impl Iterator<Item = &'a N> {
+        self.map
+            .get(weight)
+            .into_iter()
+            .flat_map(move |&idx| self.graph.neighbors_directed(idx, dir))
+            .map(move |idx| &self.graph[idx])
+    }
+
+    /// Removes a node and all of its edges. Returns false if the node didn't exist.
+    pub fn remove_node(&mut self, weight: &N) -> bool {
+        match self.map.remove(weight) {
+            Some(idx) => {
+                self.graph.remove_node(idx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Changes a node's weight, keeping its index and edges. Returns false if the node didn't exist, or if
+    /// another node already has the new weight.
+    pub fn rename_node(&mut self, weight: &N, new_weight: N) -> bool {
+        if self.map.contains_key(&new_weight) {
+            return false;
+        }
+        let Some(idx) = self.map.remove(weight) else {
+            return false;
+        };
+        self.graph[idx] = new_weight.clone();
+        self.map.insert(new_weight, idx);
+        true
+    }
+
+    /// Removes every node the predicate returns false for, along with its edges.
+    pub fn retain_nodes<F>(&mut self, mut predicate: F)
+    where
+        F: FnMut(&N) -> bool,
+    {
+        let removed: Vec<NodeIndex> = self
+            .graph
+            .node_indices()
+            .filter(|&idx| !predicate(&self.graph[idx]))
+            .collect();
+        for idx in removed {
+            if let Some(weight) = self.graph.remove_node(idx) {
+                self.map.remove(&weight);
+            }
+        }
+    }
+
+    /// Removes all edges from a to b. Returns false if there weren't any.
+    pub fn remove_edge(&mut self, a: &N, b: &N) -> bool {
+        let (Some(&ax), Some(&bx)) = (self.map.get(a), self.map.get(b)) else {
+            return false;
+        };
+
+        let mut removed = false;
+        while let Some(edge) = self.graph.find_edge(ax, bx) {
+            self.graph.remove_edge(edge);
+            removed = true;
+        }
+        removed
+    }
+
+    /// Moves every node and edge of another graph into this one.
+    /// Nodes are matched up by weight, and edges that already exist with the same weight aren't duplicated.
+    pub fn merge(&mut self, mut other: HashSetGraph<N, E, Ty>)
+    where
+        E: PartialEq,
+    {
+        let indices: HashMap<NodeIndex, NodeIndex> = other
+            .graph
+            .node_indices()
+            .map(|idx| (idx, self.get_node(&other.graph[idx])))
+            .collect();
+
+        let edges: Vec<_> = other.graph.edge_indices().collect();
+        for edge in edges {
+            let (a, b) = other
+                .graph
+                .edge_endpoints(edge)
+                .expect("an edge from edge_indices should exist");
+            let weight = other
+                .graph
+                .remove_edge(edge)
+                .expect("an edge from edge_indices should exist");
+            let (ax, bx) = (indices[&a], indices[&b]);
+            if !self
+                .graph
+                .edges_connecting(ax, bx)
+                .any(|e| *e.weight() == weight)
+            {
+                self.graph.add_edge(ax, bx, weight);
+            }
+        }
+    }
+
+    /// Like `merge`, but copies from a graph that's only borrowed. Nodes in both graphs keep their index
+    /// from this one.
+    pub fn merge_from(&mut self, other: &HashSetGraph<N, E, Ty>)
+    where
+        E: Clone + PartialEq,
+    {
+        let indices: HashMap<NodeIndex, NodeIndex> = other
+            .graph
+            .node_indices()
+            .map(|idx| (idx, self.get_node(&other.graph[idx])))
+            .collect();
+
+        for edge in other.graph.edge_references() {
+            let (ax, bx) = (indices[&edge.source()], indices[&edge.target()]);
+            if !self
+                .graph
+                .edges_connecting(ax, bx)
+                .any(|e| e.weight() == edge.weight())
+            {
+                self.graph.add_edge(ax, bx, edge.weight().clone());
+            }
+        }
+    }
+
+    /// Gets the weights of the nodes in each strongly connected component, using Tarjan's algorithm.
+    /// A component with more than one node has a cycle in it. Components come in reverse topological order,
+    /// so no component has edges to the ones after it.
+    #[must_use]
+    pub fn strongly_connected_components(&self) -> Vec<Vec<N>> {
+        petgraph::algo::tarjan_scc(&self.graph)
+            .into_iter()
+            .map(|component| {
+                component
+                    .into_iter()
+                    .map(|idx| self.graph[idx].clone())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Copies the nodes matching a predicate, and every edge between them, into a new graph.
+    #[must_use]
+    pub fn subgraph<F>(&self, predicate: F) -> HashSetGraph<N, E, Ty>
+    where
+        F: Fn(&N) -> bool,
+        E: Clone,
+    {
+        let mut subgraph = HashSetGraph::new();
+        let indices: HashMap<NodeIndex, NodeIndex> = self
+            .graph
+            .node_indices()
+            .filter(|&idx| predicate(&self.graph[idx]))
+            .map(|idx| (idx, subgraph.get_node(&self.graph[idx])))
+            .collect();
+
+        for edge in self.graph.edge_references() {
+            if let (Some(&a), Some(&b)) = (indices.get(&edge.source()), indices.get(&edge.target()))
+            {
+                subgraph.graph.add_edge(a, b, edge.weight().clone());
+            }
+        }
+        subgraph
+    }
+
+    /// Gets a new graph with the nodes in `a` that aren't in `b`, and the edges of `a` between them
+    #[must_use]
+    pub fn difference(a: &Self, b: &Self) -> Self
+    where
+        E: Clone,
+    {
+        a.subgraph(|n| !b.contains_node(n))
+    }
+
+    /// Gets a new graph with the nodes in both `a` and `b`, and the edges between them that are in both,
+    /// with the same weight
+    #[must_use]
+    pub fn intersection(a: &Self, b: &Self) -> Self
+    where
+        E: Clone + PartialEq,
+    {
+        let mut intersection = a.subgraph(|n| b.contains_node(n));
+        intersection.graph.retain_edges(|g, e| {
+            let (x, y) = g.edge_endpoints(e).unwrap();
+            b.edges_between(&g[x], &g[y]).any(|w| w == &g[e])
+        });
+        intersection
+    }
+
+    /// Gets a new graph with the nodes and edges of both `a` and `b`. Edges in both, with the same weight,
+    /// are only added once.
+    #[must_use]
+    pub fn union(a: &Self, b: &Self) -> Self
+    where
+        E: Clone + PartialEq,
+    {
+        let mut union = a.clone();
+        union.merge_from(b);
+        union
+    }
+}
+
+/// Copies the graph along with its weight to index maps, so the copy keeps the same node indices
+impl<N, E, Ty> Clone for HashSetGraph<N, E, Ty>
+where
+    Ty: petgraph::EdgeType,
+    N: Eq + std::hash::Hash + Clone,
+    E: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            graph: self.graph.clone(),
+            map: self.map.clone(),
+        }
+    }
+}
+
+impl<N, E, Ty> Default for HashSetGraph<N, E, Ty>
+where
+    Ty: petgraph::EdgeType,
+    N: Eq + std::hash::Hash + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Shows the node and edge counts, and each node's weight with its index, in index order
+impl<N, E, Ty> std::fmt::Debug for HashSetGraph<N, E, Ty>
+where
+    Ty: petgraph::EdgeType,
+    N: Eq + std::hash::Hash + Clone + std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut nodes: Vec<(NodeIndex, &N)> = self
+            .graph
+            .node_indices()
+            .map(|idx| (idx, &self.graph[idx]))
+            .collect();
+        nodes.sort_by_key(|(idx, _)| *idx);
+        f.debug_struct("HashSetGraph")
+            .field("node_count", &self.graph.node_count())
+            .field("edge_count", &self.graph.edge_count())
+            .field("map", &nodes)
+            .finish()
+    }
+}
+
+/// Summarizes the graph like `Graph(3 nodes, 4 edges)`
+impl<N, E, Ty> std::fmt::Display for HashSetGraph<N, E, Ty>
+where
+    Ty: petgraph::EdgeType,
+    N: Eq + std::hash::Hash + Clone,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Graph({} nodes, {} edges)",
+            self.graph.node_count(),
+            self.graph.edge_count()
+        )
+    }
 }
 
+/// With the `serde` feature, paths are serialized as UTF-8 strings. Paths that aren't valid UTF-8 fail to serialize.
 #[derive(Debug, Hash, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TagGraphNode {
     File { path: PathBuf },
     Directory { path: PathBuf },
+    // A symbolic link, which isn't followed. `target` is what the link points to, as written in the link.
+    Symlink { path: PathBuf, target: PathBuf },
     RootDirectory,
     RootTag,
     Tag(String),
+    // The `rating` in `rating:5`
+    TagNamespace(String),
+    // A tag that only exists in memory, computed from something other than a tag file. `source` says what
+    // it was computed from, like `extension`.
+    VirtualTag { name: String, source: String },
 }
 
-#[derive(Debug, Hash, Clone, Eq, PartialEq)]
+impl TagGraphNode {
+    /// Gets the path of a file, directory or symbolic link
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            TagGraphNode::File { path }
+            | TagGraphNode::Directory { path }
+            | TagGraphNode::Symlink { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+}
+
+/// Nodes sort by kind, in the order `RootDirectory`, `RootTag`, `Directory`, `File`, `Symlink`,
+/// `TagNamespace`, `Tag`, `VirtualTag`, and then by path or name.
+impl Ord for TagGraphNode {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        fn rank(node: &TagGraphNode) -> u8 {
+            match node {
+                TagGraphNode::RootDirectory => 0,
+                TagGraphNode::RootTag => 1,
+                TagGraphNode::Directory { .. } => 2,
+                TagGraphNode::File { .. } => 3,
+                TagGraphNode::Symlink { .. } => 4,
+                TagGraphNode::TagNamespace(_) => 5,
+                TagGraphNode::Tag(_) => 6,
+                TagGraphNode::VirtualTag { .. } => 7,
+            }
+        }
+
+        match (self, other) {
+            (TagGraphNode::Directory { path: a }, TagGraphNode::Directory { path: b })
+            | (TagGraphNode::File { path: a }, TagGraphNode::File { path: b }) => a.cmp(b),
+            (
+                TagGraphNode::Symlink { path: a, target: x },
+                TagGraphNode::Symlink { path: b, target: y },
+            ) => (a, x).cmp(&(b, y)),
+            (TagGraphNode::TagNamespace(a), TagGraphNode::TagNamespace(b))
+            | (TagGraphNode::Tag(a), TagGraphNode::Tag(b)) => a.cmp(b),
+            (
+                TagGraphNode::VirtualTag { name: a, source: x },
+                TagGraphNode::VirtualTag { name: b, source: y },
+            ) => (a, x).cmp(&(b, y)),
+            _ => rank(self).cmp(&rank(other)),
+        }
+    }
+}
+
+impl PartialOrd for TagGraphNode {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Debug, Hash, Clone, Eq, PartialEq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Relation {
     // Directory/File A's parent is B
     Parent,
@@ -249,4 +3308,20 @@ pub enum Relation {
     HasTag,
     // Tag A has been assigned to B
     TagAssignedTo,
+    // Directory/File A has tag B through a directory above it
+    InheritedTag,
+    // Tag A has been assigned to a directory above B
+    InheritedTagAssignedTo,
+    // Tag A's parent tag is B
+    ParentTag,
+    // Tag B is nested under tag A
+    ChildTag,
+    // Directory/File A opts out of tag B
+    ExcludesTag,
+    // Tag A is a value in namespace B
+    HasNamespace,
+    // Namespace A has the value tag B
+    NamespaceValue,
+    // File A is a copy or alias of file B
+    Alias,
 }
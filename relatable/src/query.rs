@@ -0,0 +1,202 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::PathBuf,
+};
+
+use petgraph::Directed;
+
+use crate::{node_tags, Error, HashSetGraph, Relation, TagGraphNode};
+
+/// A parsed tag expression, e.g. `sketch and (wip or final) and not archived`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Tag(String),
+}
+
+impl Expr {
+    /// Checks whether a set of tags satisfies the expression
+    pub fn matches(&self, tags: &HashSet<&str>) -> bool {
+        match self {
+            Expr::And(a, b) => a.matches(tags) && b.matches(tags),
+            Expr::Or(a, b) => a.matches(tags) || b.matches(tags),
+            Expr::Not(a) => !a.matches(tags),
+            Expr::Tag(tag) => tags.contains(tag.as_str()),
+        }
+    }
+}
+
+/// Parses a tag expression.
+/// `not` binds tighter than `and`, which binds tighter than `or`. Parentheses group, and tags
+/// containing spaces or keywords can be written in double quotes (`"in progress"`).
+pub fn parse(input: &str) -> Result<Expr, Error> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+        end: input.len(),
+    };
+    let expr = parser.or_expr()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some((position, _)) => Err(parse_error(
+            *position,
+            "expected `and`, `or` or end of query",
+        )),
+    }
+}
+
+/// Gets the paths of every tagged file matching an expression, in sorted order.
+/// Tags assigned to a file's ancestor directories count for the file.
+pub fn evaluate(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    expr: &Expr,
+) -> Vec<PathBuf> {
+    let mut paths = BTreeSet::new();
+    for node in tag_graph.graph.node_indices() {
+        let TagGraphNode::File { path } = &tag_graph.graph[node] else {
+            continue;
+        };
+        let tags = node_tags(tag_graph, node, true);
+        if !tags.is_empty() && expr.matches(&tags) {
+            paths.insert(path.clone());
+        }
+    }
+    paths.into_iter().collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Tag(String),
+}
+
+fn parse_error(position: usize, message: &str) -> Error {
+    Error::QueryParse {
+        position,
+        message: message.to_string(),
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, Error> {
+    let mut tokens = vec![];
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push((start, Token::LParen));
+            }
+            ')' => {
+                chars.next();
+                tokens.push((start, Token::RParen));
+            }
+            '"' => {
+                chars.next();
+                let mut tag = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, escaped)) => tag.push(escaped),
+                            None => return Err(parse_error(start, "unterminated quoted tag")),
+                        },
+                        Some((_, c)) => tag.push(c),
+                        None => return Err(parse_error(start, "unterminated quoted tag")),
+                    }
+                }
+                if tag.is_empty() {
+                    return Err(parse_error(start, "empty quoted tag"));
+                }
+                tokens.push((start, Token::Tag(tag)));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&(_, c)) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                let token = match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Tag(word),
+                };
+                tokens.push((start, token));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [(usize, Token)],
+    pos: usize,
+    /// Position reported for errors at the end of the input
+    end: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a (usize, Token)> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        match self.peek() {
+            Some((_, t)) if t == token => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or_expr(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.and_expr()?;
+        while self.eat(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and_expr()?));
+        }
+        Ok(expr)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.unary()?;
+        while self.eat(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, Error> {
+        let Some((position, token)) = self.peek() else {
+            return Err(parse_error(self.end, "expected a tag"));
+        };
+        self.pos += 1;
+        match token {
+            Token::Not => Ok(Expr::Not(Box::new(self.unary()?))),
+            Token::LParen => {
+                let expr = self.or_expr()?;
+                if self.eat(&Token::RParen) {
+                    Ok(expr)
+                } else {
+                    let position = self.peek().map_or(self.end, |(p, _)| *p);
+                    Err(parse_error(position, "expected `)`"))
+                }
+            }
+            Token::Tag(tag) => Ok(Expr::Tag(tag.clone())),
+            _ => Err(parse_error(*position, "expected a tag")),
+        }
+    }
+}
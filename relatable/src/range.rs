@@ -0,0 +1,59 @@
+use std::{
+    collections::BTreeSet,
+    ops::{Bound, RangeBounds},
+    path::PathBuf,
+};
+
+use petgraph::Directed;
+
+use crate::{files_with_attr, values_of, HashSetGraph, Relation, TagGraphNode};
+
+/// Parses the value of a namespaced tag as a number.
+/// Integers, decimals, negative numbers and leading zeros (`007`) are accepted; infinities and NaN aren't.
+pub fn parse_attr_value(value: &str) -> Option<f64> {
+    value.trim().parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+/// Gets the paths of every file whose value for a namespace falls within a range, e.g. `rating` in `3..=5`.
+/// Values that aren't numbers are skipped; see `unparseable_attr_values`.
+pub fn files_with_attr_range<T, R>(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    namespace: &str,
+    range: R,
+) -> Vec<PathBuf>
+where
+    T: Copy + Into<f64>,
+    R: RangeBounds<T>,
+{
+    let range = (to_f64(range.start_bound()), to_f64(range.end_bound()));
+
+    let mut paths = BTreeSet::new();
+    for value in values_of(tag_graph, namespace) {
+        match parse_attr_value(&value) {
+            Some(number) if range.contains(&number) => {
+                paths.extend(files_with_attr(tag_graph, namespace, &value));
+            }
+            _ => (),
+        }
+    }
+    paths.into_iter().collect()
+}
+
+/// Gets the values of a namespace that `files_with_attr_range` can't compare, in sorted order.
+pub fn unparseable_attr_values(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    namespace: &str,
+) -> Vec<String> {
+    values_of(tag_graph, namespace)
+        .into_iter()
+        .filter(|value| parse_attr_value(value).is_none())
+        .collect()
+}
+
+fn to_f64<T: Copy + Into<f64>>(bound: Bound<&T>) -> Bound<f64> {
+    match bound {
+        Bound::Included(v) => Bound::Included((*v).into()),
+        Bound::Excluded(v) => Bound::Excluded((*v).into()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
@@ -0,0 +1,142 @@
+use std::{collections::HashMap, hash::Hash};
+
+use petgraph::{
+    graph::NodeIndex,
+    visit::{EdgeRef, IntoEdgeReferences},
+    Directed, EdgeType,
+};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{node_kind, node_label, Error, HashSetGraph, Relation, TagGraphNode};
+
+/// Graphs are stored as a list of node weights, and edges referring to positions in that list.
+/// The weight to index map is rebuilt when deserializing.
+#[derive(Serialize)]
+struct SerializedGraph<'a, N, E> {
+    nodes: Vec<&'a N>,
+    edges: Vec<(usize, usize, &'a E)>,
+}
+
+#[derive(Deserialize)]
+struct DeserializedGraph<N, E> {
+    nodes: Vec<N>,
+    edges: Vec<(usize, usize, E)>,
+}
+
+impl<N, E, Ty> Serialize for HashSetGraph<N, E, Ty>
+where
+    Ty: petgraph::EdgeType,
+    N: Eq + Hash + Clone + Serialize,
+    E: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // StableGraph indices can have holes in them, so map them onto contiguous positions
+        let positions: HashMap<NodeIndex, usize> = self
+            .graph
+            .node_indices()
+            .enumerate()
+            .map(|(position, idx)| (idx, position))
+            .collect();
+
+        let nodes = self.graph.node_indices().map(|n| &self.graph[n]).collect();
+        let edges = self
+            .graph
+            .edge_indices()
+            .map(|e| {
+                let (a, b) = self
+                    .graph
+                    .edge_endpoints(e)
+                    .expect("an edge from edge_indices should exist");
+                (positions[&a], positions[&b], &self.graph[e])
+            })
+            .collect();
+
+        SerializedGraph { nodes, edges }.serialize(serializer)
+    }
+}
+
+impl<'de, N, E, Ty> Deserialize<'de> for HashSetGraph<N, E, Ty>
+where
+    Ty: EdgeType,
+    N: Eq + Hash + Clone + Deserialize<'de>,
+    E: Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let serialized = DeserializedGraph::<N, E>::deserialize(deserializer)?;
+
+        let mut graph = HashSetGraph::new();
+        let mut indices = Vec::with_capacity(serialized.nodes.len());
+        for weight in serialized.nodes {
+            if graph.map.contains_key(&weight) {
+                return Err(de::Error::custom(
+                    "graph contains the same node more than once",
+                ));
+            }
+            indices.push(graph.get_node_move(weight));
+        }
+
+        for (a, b, weight) in serialized.edges {
+            let (Some(&ax), Some(&bx)) = (indices.get(a), indices.get(b)) else {
+                return Err(de::Error::custom(format!(
+                    "edge ({}, {}) refers to a node that doesn't exist",
+                    a, b
+                )));
+            };
+            graph.graph.add_edge(ax, bx, weight);
+        }
+
+        Ok(graph)
+    }
+}
+
+#[derive(Serialize)]
+struct JsonGraph<'a> {
+    nodes: Vec<JsonNode>,
+    edges: Vec<JsonEdge<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonNode {
+    id: usize,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    label: String,
+}
+
+#[derive(Serialize)]
+struct JsonEdge<'a> {
+    source: usize,
+    target: usize,
+    relation: &'a Relation,
+}
+
+/// Writes a graph as JSON lists of nodes and edges, for tools like D3.js:
+/// `{"nodes": [{"id": 0, "type": "file", "label": "photo.jpg"}], "edges": [{"source": 1, "target": 0, "relation": "Child"}]}`.
+/// Node ids are the graph's node indices, and labels come from `node_label`.
+pub fn to_json(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+) -> Result<String, Error> {
+    let nodes = tag_graph
+        .graph
+        .node_indices()
+        .map(|idx| {
+            let node = &tag_graph.graph[idx];
+            JsonNode {
+                id: idx.index(),
+                kind: node_kind(node),
+                label: node_label(node),
+            }
+        })
+        .collect();
+    let edges = tag_graph
+        .graph
+        .edge_references()
+        .map(|e| JsonEdge {
+            source: e.source().index(),
+            target: e.target().index(),
+            relation: e.weight(),
+        })
+        .collect();
+
+    Ok(serde_json::to_string(&JsonGraph { nodes, edges })?)
+}
@@ -0,0 +1,181 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use petgraph::{
+    graph::NodeIndex,
+    visit::{EdgeRef, IntoEdgeReferences},
+    Directed,
+};
+use rusqlite::{params, Connection};
+
+use crate::{node_kind, Error, HashSetGraph, Relation, TagGraphNode};
+
+const SCHEMA: &str = "
+    CREATE TABLE nodes (
+        id INTEGER PRIMARY KEY,
+        kind TEXT NOT NULL,
+        path TEXT,
+        target TEXT,
+        name TEXT,
+        source TEXT
+    );
+    CREATE TABLE edges (
+        from_id INTEGER NOT NULL REFERENCES nodes (id),
+        to_id INTEGER NOT NULL REFERENCES nodes (id),
+        relation TEXT NOT NULL
+    );
+";
+
+/// Reads a graph saved with `save_to_db`
+pub fn get_tagged_files_from_db(
+    db_path: impl AsRef<Path>,
+) -> Result<HashSetGraph<TagGraphNode, Relation, Directed>, Error> {
+    let connection = Connection::open(db_path)?;
+    let mut tag_graph = HashSetGraph::new();
+
+    let mut ids = HashMap::new();
+    let mut statement =
+        connection.prepare("SELECT id, kind, path, target, name, source FROM nodes ORDER BY id")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let kind: String = row.get(1)?;
+        let path = |column| -> Result<PathBuf, Error> {
+            let path: Option<String> = row.get(column)?;
+            path.map(PathBuf::from)
+                .ok_or_else(|| invalid_row(format!("{} node {} has no path", kind, id)))
+        };
+        let text = |column| -> Result<String, Error> {
+            let text: Option<String> = row.get(column)?;
+            text.ok_or_else(|| invalid_row(format!("{} node {} has no name", kind, id)))
+        };
+        let node = match kind.as_str() {
+            "file" => TagGraphNode::File { path: path(2)? },
+            "directory" => TagGraphNode::Directory { path: path(2)? },
+            "symlink" => TagGraphNode::Symlink {
+                path: path(2)?,
+                target: path(3)?,
+            },
+            "root_directory" => TagGraphNode::RootDirectory,
+            "root_tag" => TagGraphNode::RootTag,
+            "tag" => TagGraphNode::Tag(text(4)?),
+            "tag_namespace" => TagGraphNode::TagNamespace(text(4)?),
+            "virtual_tag" => TagGraphNode::VirtualTag {
+                name: text(4)?,
+                source: text(5)?,
+            },
+            _ => {
+                return Err(invalid_row(format!(
+                    "node {} has unknown kind {:?}",
+                    id, kind
+                )))
+            }
+        };
+        if tag_graph.map.contains_key(&node) {
+            return Err(invalid_row(format!(
+                "{:?} is in the nodes more than once",
+                node
+            )));
+        }
+        ids.insert(id, tag_graph.get_node_move(node));
+    }
+
+    let mut statement = connection.prepare("SELECT from_id, to_id, relation FROM edges")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let (from, to, relation): (i64, i64, String) = (row.get(0)?, row.get(1)?, row.get(2)?);
+        let (Some(&a), Some(&b)) = (ids.get(&from), ids.get(&to)) else {
+            return Err(invalid_row(format!(
+                "edge from {} to {} refers to a missing node",
+                from, to
+            )));
+        };
+        let relation = parse_relation(&relation)
+            .ok_or_else(|| invalid_row(format!("unknown relation {:?}", relation)))?;
+        tag_graph.graph.add_edge(a, b, relation);
+    }
+    Ok(tag_graph)
+}
+
+/// Saves a graph to a SQLite database, replacing any graph saved there before. Nodes go in a `nodes` table
+/// with their `kind` as named by `to_json`, and edges in an `edges` table of `from_id`, `to_id` and
+/// `relation`. Fails with `Error::NonUtf8Path` for paths that aren't valid UTF-8.
+pub fn save_to_db(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    db_path: impl AsRef<Path>,
+) -> Result<(), Error> {
+    let mut connection = Connection::open(db_path)?;
+    let transaction = connection.transaction()?;
+    transaction.execute_batch("DROP TABLE IF EXISTS edges; DROP TABLE IF EXISTS nodes;")?;
+    transaction.execute_batch(SCHEMA)?;
+
+    let mut ids: HashMap<NodeIndex, i64> = HashMap::new();
+    {
+        let mut insert = transaction.prepare(
+            "INSERT INTO nodes (id, kind, path, target, name, source) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        // Sorted, so saving the same graph twice gives the same ids
+        let mut nodes: Vec<NodeIndex> = tag_graph.graph.node_indices().collect();
+        nodes.sort_by(|&a, &b| tag_graph.graph[a].cmp(&tag_graph.graph[b]));
+        for (id, idx) in (1..).zip(nodes) {
+            let node = &tag_graph.graph[idx];
+            let (path, target, name, source) = match node {
+                TagGraphNode::File { path } | TagGraphNode::Directory { path } => {
+                    (Some(utf8(path)?), None, None, None)
+                }
+                TagGraphNode::Symlink { path, target } => {
+                    (Some(utf8(path)?), Some(utf8(target)?), None, None)
+                }
+                TagGraphNode::RootDirectory | TagGraphNode::RootTag => (None, None, None, None),
+                TagGraphNode::Tag(name) | TagGraphNode::TagNamespace(name) => {
+                    (None, None, Some(name.as_str()), None)
+                }
+                TagGraphNode::VirtualTag { name, source } => {
+                    (None, None, Some(name.as_str()), Some(source.as_str()))
+                }
+            };
+            insert.execute(params![id, node_kind(node), path, target, name, source])?;
+            ids.insert(idx, id);
+        }
+
+        let mut insert = transaction
+            .prepare("INSERT INTO edges (from_id, to_id, relation) VALUES (?1, ?2, ?3)")?;
+        for edge in tag_graph.graph.edge_references() {
+            let relation = format!("{:?}", edge.weight());
+            insert.execute(params![ids[&edge.source()], ids[&edge.target()], relation])?;
+        }
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+fn utf8(path: &Path) -> Result<&str, Error> {
+    path.to_str().ok_or_else(|| Error::NonUtf8Path {
+        path: path.to_path_buf(),
+    })
+}
+
+fn invalid_row(message: String) -> Error {
+    Error::InvalidDatabase { message }
+}
+
+/// Reads a relation as written by `save_to_db`
+fn parse_relation(name: &str) -> Option<Relation> {
+    Some(match name {
+        "Parent" => Relation::Parent,
+        "Child" => Relation::Child,
+        "HasTag" => Relation::HasTag,
+        "TagAssignedTo" => Relation::TagAssignedTo,
+        "InheritedTag" => Relation::InheritedTag,
+        "InheritedTagAssignedTo" => Relation::InheritedTagAssignedTo,
+        "ParentTag" => Relation::ParentTag,
+        "ChildTag" => Relation::ChildTag,
+        "ExcludesTag" => Relation::ExcludesTag,
+        "HasNamespace" => Relation::HasNamespace,
+        "NamespaceValue" => Relation::NamespaceValue,
+        "Alias" => Relation::Alias,
+        _ => return None,
+    })
+}
@@ -0,0 +1,227 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{parse_tagfile_line, strip_bom, Error, TagOperation};
+
+/// A tag file as it's written on disk, for editing it without disturbing the rest of the file.
+/// Comments, blank lines, ordering and line endings are kept, so saving a document that wasn't
+/// changed writes back exactly what was read.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagfileDocument {
+    lines: Vec<DocumentLine>,
+    /// Whether the file starts with a UTF-8 byte order mark
+    bom: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DocumentLine {
+    text: String,
+    /// `\n`, `\r\n`, or nothing for a last line without a line break
+    ending: &'static str,
+    operation: Option<TagOperation>,
+}
+
+impl DocumentLine {
+    fn new(text: String, ending: &'static str) -> Self {
+        DocumentLine {
+            operation: parse_tagfile_line(&text),
+            text,
+            ending,
+        }
+    }
+}
+
+impl TagfileDocument {
+    pub fn parse(contents: &str) -> Self {
+        let bom = contents.len() != strip_bom(contents).len();
+        let lines = strip_bom(contents)
+            .split_inclusive('\n')
+            .map(|line| {
+                if let Some(text) = line.strip_suffix("\r\n") {
+                    DocumentLine::new(text.to_string(), "\r\n")
+                } else if let Some(text) = line.strip_suffix('\n') {
+                    DocumentLine::new(text.to_string(), "\n")
+                } else {
+                    DocumentLine::new(line.to_string(), "")
+                }
+            })
+            .collect();
+        TagfileDocument { lines, bom }
+    }
+
+    /// Reads a tag file. A file that doesn't exist yet reads as an empty document.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(Self::parse(&contents)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(source) => Err(Error::TagfileRead {
+                path: path.to_path_buf(),
+                source,
+            }),
+        }
+    }
+
+    /// Writes the document to a tag file with `write_contents_atomic`
+    pub fn save(&self, path: &Path, backup: bool) -> Result<(), Error> {
+        write_contents_atomic(path, self.to_string().as_bytes(), backup)
+    }
+
+    /// Gets the tag operations in the document, in order
+    pub fn operations(&self) -> impl Iterator<Item = &TagOperation> {
+        self.lines.iter().filter_map(|line| line.operation.as_ref())
+    }
+
+    pub fn contains(&self, operation: &TagOperation) -> bool {
+        self.operations().any(|o| o == operation)
+    }
+
+    /// Checks whether the document has nothing but blank lines
+    pub fn is_blank(&self) -> bool {
+        self.lines.iter().all(|line| line.text.trim().is_empty())
+    }
+
+    /// Appends a line to the end of the document, using the line ending of the first line
+    pub fn push_line(&mut self, text: &str) {
+        let ending = self.lines.first().map_or("\n", |line| match line.ending {
+            "" => "\n",
+            ending => ending,
+        });
+        if let Some(last) = self.lines.last_mut() {
+            if last.ending.is_empty() {
+                last.ending = ending;
+            }
+        }
+        self.lines.push(DocumentLine::new(text.to_string(), ending));
+    }
+
+    /// Removes every line with an operation. Returns whether any lines were removed.
+    pub fn remove_operation(&mut self, operation: &TagOperation) -> bool {
+        let before = self.lines.len();
+        self.lines
+            .retain(|line| line.operation.as_ref() != Some(operation));
+        self.lines.len() != before
+    }
+
+    /// Rewrites the lines with operations on them, leaving comments and blank lines alone.
+    /// `f` returns `None` to keep a line, `Some(None)` to remove it, or `Some(Some(text))` to replace it.
+    /// Returns whether anything changed.
+    pub fn edit_operations<F>(&mut self, mut f: F) -> bool
+    where
+        F: FnMut(&TagOperation) -> Option<Option<String>>,
+    {
+        let mut changed = false;
+        let lines = std::mem::take(&mut self.lines);
+        for line in lines {
+            match line.operation.as_ref().and_then(&mut f) {
+                Some(Some(text)) => {
+                    changed |= text != line.text;
+                    self.lines.push(DocumentLine::new(text, line.ending));
+                }
+                Some(None) => changed = true,
+                None => self.lines.push(line),
+            }
+        }
+        changed
+    }
+}
+
+impl std::fmt::Display for TagfileDocument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.bom {
+            f.write_str("\u{feff}")?;
+        }
+        for line in &self.lines {
+            f.write_str(&line.text)?;
+            f.write_str(line.ending)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a tag file without ever leaving it half written.
+/// The contents go to `<path>.tmp` first, which is synced to disk and then renamed over the original.
+/// A leftover `.tmp` from an earlier failed write is overwritten. With `backup` set, the previous
+/// contents are kept in `<path>.bak`.
+pub fn write_contents_atomic(path: &Path, contents: &[u8], backup: bool) -> Result<(), Error> {
+    let tmp = with_suffix(path, ".tmp");
+    let result = (|| -> io::Result<()> {
+        let mut file = File::create(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        if backup && path.exists() {
+            fs::copy(path, with_suffix(path, ".bak"))?;
+        }
+        fs::rename(&tmp, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    Ok(result?)
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct JsonTagfile {
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    attrs: std::collections::BTreeMap<String, serde_json::Value>,
+}
+
+/// Reads a JSON sidecar like `{"tags": ["beach"], "attrs": {"rating": 5}}`.
+/// Attributes become namespaced tags (`rating:5`); ones whose value isn't a string, number or boolean are
+/// skipped with a warning. Fails with `Error::JsonTagfile` if the file isn't valid.
+#[cfg(feature = "serde")]
+pub fn read_json_tagfile(path: &Path) -> Result<Vec<TagOperation>, Error> {
+    use serde_json::Value;
+
+    let contents = crate::read_tagfile_contents(path)?;
+    let parsed: JsonTagfile = serde_json::from_str(&contents).map_err(|e| Error::JsonTagfile {
+        path: path.to_path_buf(),
+        offset: byte_offset(&contents, e.line(), e.column()),
+        message: e.to_string(),
+    })?;
+
+    let mut operations: Vec<TagOperation> = parsed
+        .tags
+        .into_iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .map(TagOperation::Add)
+        .collect();
+    for (name, value) in parsed.attrs {
+        let value = match value {
+            Value::String(s) => s,
+            Value::Number(n) => n.to_string(),
+            Value::Bool(b) => b.to_string(),
+            _ => {
+                log::warn!("Attribute {:?} in {:?} isn't a single value", name, path);
+                continue;
+            }
+        };
+        operations.push(TagOperation::Add(format!("{}:{}", name, value)));
+    }
+    Ok(operations)
+}
+
+/// Turns a 1-based line and column from serde_json into a byte offset
+#[cfg(feature = "serde")]
+fn byte_offset(contents: &str, line: usize, column: usize) -> usize {
+    let line_start: usize = contents
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum();
+    (line_start + column.saturating_sub(1)).min(contents.len())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
@@ -0,0 +1,517 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use log::{error, trace};
+use notify::{
+    event::ModifyKind, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _,
+};
+use petgraph::{graph::NodeIndex, visit::EdgeRef, Directed, Direction};
+
+use crate::{
+    apply_tagfile_targets, canonicalize_dir, edit::find_tagfiles, effective_tags, inherit_tags,
+    outermost, read_tagfile_targets, rescan_subtree_with, scan, DirectedHashSetGraph, Error,
+    HashSetGraph, Relation, ScanOptions, TagGraphNode,
+};
+
+/// How long no more changes have to come in before a burst of them is applied together
+pub const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Keeps a graph up to date with the tag files under a root until it's stopped or dropped
+pub struct Watcher {
+    watcher: Option<RecommendedWatcher>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watcher {
+    /// Watches everything under `root`, and calls `apply` with each burst of events once `DEBOUNCE` has passed
+    /// without another one
+    fn spawn(
+        root: &Path,
+        mut apply: impl FnMut(Vec<Event>) + Send + 'static,
+    ) -> Result<Self, Error> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event| match event {
+            // Nobody listening means the watcher is stopping
+            Ok(event) => drop(sender.send(event)),
+            Err(e) => error!("Couldn't watch for changes: {}", e),
+        })?;
+        watcher.watch(root, RecursiveMode::Recursive)?;
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut pending = vec![];
+                while !stop.load(Ordering::Relaxed) {
+                    match events.recv_timeout(DEBOUNCE) {
+                        Ok(event) => pending.push(event),
+                        Err(RecvTimeoutError::Timeout) if !pending.is_empty() => {
+                            apply(std::mem::take(&mut pending))
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+            })
+        };
+
+        Ok(Watcher {
+            watcher: Some(watcher),
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Stops watching, waiting for an update in progress to finish
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.watcher.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Checks whether an event can add, remove or move something, rather than only change what's in a file
+fn is_structural(event: &Event) -> bool {
+    event.need_rescan()
+        || matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_))
+        )
+}
+
+/// Checks whether a path is a tag file that's read when scanning
+fn is_scanned_tagfile(path: &Path, options: &ScanOptions) -> bool {
+    options.is_tagfile(path)
+        && !options.is_write_leftover(path)
+        && (cfg!(feature = "serde") || !options.is_json_tagfile(path))
+}
+
+/// Watches the tag files under `root`, which `graph` should have been built from with `options`.
+/// When tag files are created, changed or deleted, including by moving or deleting the directories they're in,
+/// only the tags of the paths they apply to are updated, and then `cb` is called.
+pub fn watch(
+    root: impl AsRef<Path>,
+    options: ScanOptions,
+    graph: Arc<Mutex<HashSetGraph<TagGraphNode, Relation, Directed>>>,
+    cb: impl Fn() + Send + 'static,
+) -> Result<Watcher, Error> {
+    let root = canonicalize_dir(root.as_ref())?;
+    let mut known: BTreeSet<PathBuf> = find_tagfiles(&root, &options).into_iter().collect();
+
+    Watcher::spawn(&root.clone(), move |events| {
+        let mut changed = BTreeSet::new();
+        for event in &events {
+            if event.need_rescan() {
+                changed.extend(known.iter().cloned());
+                changed.extend(find_tagfiles(&root, &options));
+                continue;
+            }
+            for path in &event.paths {
+                if is_scanned_tagfile(path, &options) {
+                    changed.insert(path.clone());
+                } else if is_structural(event) {
+                    // A directory that moved or was deleted takes its tag files with it
+                    changed.extend(known.iter().filter(|t| t.starts_with(path)).cloned());
+                    if path.is_dir() {
+                        changed.extend(find_tagfiles(path, &options));
+                    }
+                }
+            }
+        }
+        changed.retain(|path| path.starts_with(&root));
+        if changed.is_empty() {
+            return;
+        }
+
+        trace!("Tag files changed: {:?}", changed);
+        {
+            let mut graph = graph.lock().unwrap_or_else(|e| e.into_inner());
+            for tagfile in &changed {
+                if let Err(e) = update_tagfile(&mut graph, tagfile, &options) {
+                    error!("Couldn't update the tags from {:?}: {}", tagfile, e);
+                }
+            }
+            inherit_tags(&mut graph);
+        }
+        for tagfile in changed {
+            if tagfile.exists() {
+                known.insert(tagfile);
+            } else {
+                known.remove(&tagfile);
+            }
+        }
+        cb();
+    })
+}
+
+/// When each tag file a graph was built from was last modified, for updating the graph with `rebuild_changed`
+#[derive(Debug, Default, Clone)]
+pub struct GraphBuildMetadata {
+    pub tagfiles: HashMap<PathBuf, SystemTime>,
+}
+
+/// Like `scan`, along with the metadata `rebuild_changed` needs to update the graph later
+pub fn get_tagged_files_with_metadata(
+    root: impl AsRef<Path>,
+    options: &ScanOptions,
+) -> Result<
+    (
+        DirectedHashSetGraph<TagGraphNode, Relation>,
+        GraphBuildMetadata,
+    ),
+    Error,
+> {
+    let root = canonicalize_dir(root.as_ref())?;
+    // Read before scanning, so anything changed during the scan is picked up by the next rebuild
+    let tagfiles = modified_times(&root, options);
+    let (graph, _) = scan(&root, options)?;
+    Ok((graph, GraphBuildMetadata { tagfiles }))
+}
+
+/// Rereads only the tag files under `root` that were created, changed or deleted since `meta` was recorded,
+/// like `watch` does, and updates `meta`. Returns how many files and directories had their tags reapplied.
+/// New or removed files aren't picked up; see `rescan_subtree` for those. Tags nothing has any more are left
+/// in the graph until `prune_orphan_tags` removes them. `options` should be the ones the graph was built with.
+pub fn rebuild_changed(
+    root: impl AsRef<Path>,
+    options: &ScanOptions,
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+    meta: &mut GraphBuildMetadata,
+) -> Result<usize, Error> {
+    let root = canonicalize_dir(root.as_ref())?;
+    let current = modified_times(&root, options);
+    let changed: BTreeSet<&PathBuf> = current
+        .iter()
+        .filter(|(path, time)| meta.tagfiles.get(*path) != Some(*time))
+        .map(|(path, _)| path)
+        .chain(
+            meta.tagfiles
+                .keys()
+                .filter(|path| path.starts_with(&root) && !current.contains_key(*path)),
+        )
+        .collect();
+    if changed.is_empty() {
+        return Ok(0);
+    }
+
+    trace!("Tag files changed: {:?}", changed);
+    let mut updated = HashSet::new();
+    for tagfile in changed {
+        updated.extend(update_tagfile(tag_graph, tagfile, options)?);
+    }
+    inherit_tags(tag_graph);
+    meta.tagfiles.retain(|path, _| !path.starts_with(&root));
+    meta.tagfiles.extend(current);
+    Ok(updated.len())
+}
+
+fn modified_times(root: &Path, options: &ScanOptions) -> HashMap<PathBuf, SystemTime> {
+    find_tagfiles(root, options)
+        .into_iter()
+        .filter_map(|path| {
+            let modified = path.metadata().and_then(|m| m.modified()).ok()?;
+            Some((path, modified))
+        })
+        .collect()
+}
+
+/// Reapplies the tags of everything a tag file applies to, after it's been created, changed or deleted,
+/// and returns those nodes. `inherit_tags` needs to run afterwards to pass the changes down.
+fn update_tagfile(
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+    tagfile: &Path,
+    options: &ScanOptions,
+) -> Result<Vec<NodeIndex>, Error> {
+    let (Some(dir), Some(name)) = (tagfile.parent(), tagfile.file_name()) else {
+        return Err(Error::InvalidPath {
+            path: tagfile.to_path_buf(),
+        });
+    };
+    let Some(&dir_node) = tag_graph.map.get(&TagGraphNode::Directory {
+        path: dir.to_path_buf(),
+    }) else {
+        return Ok(vec![]);
+    };
+
+    // Any tag file can have `glob:` lines reaching the other files in its directory, before or after the change
+    let mut affected: Vec<NodeIndex> = children(tag_graph, dir_node).collect();
+    if *name == *options.dir_tagfile() {
+        affected.push(dir_node);
+    }
+
+    let mut listings = HashMap::new();
+    let mut tagfiles = BTreeSet::new();
+    for &node in &affected {
+        clear_tags(tag_graph, node);
+        let path = match &tag_graph.graph[node] {
+            TagGraphNode::File { path }
+            | TagGraphNode::Directory { path }
+            | TagGraphNode::Symlink { path, .. } => path,
+            _ => continue,
+        };
+        if matches!(tag_graph.graph[node], TagGraphNode::Directory { .. }) {
+            let dir_tagfile = path.join(options.dir_tagfile());
+            if dir_tagfile.exists() {
+                tagfiles.insert(dir_tagfile);
+            }
+        }
+        let Some(parent) = path.parent() else {
+            continue;
+        };
+        // Tag files only apply to what they match, so the rest of the directory's tag files are reapplied
+        let listing = listings
+            .entry(parent.to_path_buf())
+            .or_insert_with(|| tagfiles_in(parent, options));
+        tagfiles.extend(listing.iter().cloned());
+    }
+
+    let tag_root = tag_graph.get_node(&TagGraphNode::RootTag);
+    for tagfile in tagfiles {
+        add_tagfile_to_graph(tag_graph, tag_root, &tagfile, options)?;
+    }
+    Ok(affected)
+}
+
+/// Gets the tag files in a directory
+fn tagfiles_in(dir: &Path, options: &ScanOptions) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|path| is_scanned_tagfile(path, options))
+        .collect()
+}
+
+fn children(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    node: NodeIndex,
+) -> impl Iterator<Item = NodeIndex> + '_ {
+    tag_graph
+        .graph
+        .edges(node)
+        .filter(|e| *e.weight() == Relation::Child)
+        .map(|e| e.target())
+}
+
+/// Removes every tag directly assigned to or excluded from a node
+fn clear_tags(tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>, node: NodeIndex) {
+    let edges: Vec<_> = tag_graph
+        .graph
+        .edges(node)
+        .filter(|e| matches!(e.weight(), Relation::HasTag | Relation::ExcludesTag))
+        .chain(
+            tag_graph
+                .graph
+                .edges_directed(node, Direction::Incoming)
+                .filter(|e| *e.weight() == Relation::TagAssignedTo),
+        )
+        .map(|e| e.id())
+        .collect();
+    for edge in edges {
+        tag_graph.graph.remove_edge(edge);
+    }
+}
+
+/// A change `TagWatcher` made to its graph
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphChange {
+    NodeAdded(TagGraphNode),
+    NodeRemoved(TagGraphNode),
+    /// A file or directory got a tag, whether its own or inherited
+    TagAttached {
+        node: TagGraphNode,
+        tag: String,
+    },
+    /// A file or directory lost a tag
+    TagDetached {
+        node: TagGraphNode,
+        tag: String,
+    },
+}
+
+/// Owns the graph of a root and keeps it up to date as files, directories and tag files under the root are
+/// created, removed, renamed or changed, until it's stopped or dropped. Changes are applied once none have
+/// come in for `DEBOUNCE`, so a burst of them is applied together. Only the directories that changed are
+/// scanned again, like `rescan_subtree`.
+pub struct TagWatcher {
+    graph: Arc<Mutex<DirectedHashSetGraph<TagGraphNode, Relation>>>,
+    changes: Receiver<GraphChange>,
+    watcher: Watcher,
+}
+
+impl TagWatcher {
+    /// Scans `root` and starts watching it. `options` are used for the scan and every update after it, and
+    /// need `PathMode::Canonical`.
+    pub fn new(root: impl AsRef<Path>, options: ScanOptions) -> Result<Self, Error> {
+        let root = canonicalize_dir(root.as_ref())?;
+        let (graph, _) = scan(&root, &options)?;
+        let graph = Arc::new(Mutex::new(graph));
+        let (sender, changes) = mpsc::channel();
+
+        let watcher = {
+            let graph = graph.clone();
+            Watcher::spawn(&root.clone(), move |events| {
+                let mut changed = BTreeSet::new();
+                for event in &events {
+                    if event.need_rescan() {
+                        changed.insert(root.clone());
+                        continue;
+                    }
+                    for path in &event.paths {
+                        // Only the directory a path is in lists it, so that's the one to scan again
+                        if is_structural(event) || options.is_tagfile(path) {
+                            changed.extend(path.parent().map(Path::to_path_buf));
+                        }
+                    }
+                }
+                changed.retain(|dir| dir.starts_with(&root));
+
+                let mut graph = graph.lock().unwrap_or_else(|e| e.into_inner());
+                for dir in outermost(&changed) {
+                    let before = subtree_state(&graph, dir);
+                    if let Err(e) = rescan_subtree_with(&mut graph, &root, dir, &options) {
+                        error!("Couldn't update {:?}: {}", dir, e);
+                        continue;
+                    }
+                    let after = subtree_state(&graph, dir);
+                    for change in state_changes(before, after) {
+                        // Nobody listening is fine
+                        let _ = sender.send(change);
+                    }
+                }
+            })?
+        };
+
+        Ok(TagWatcher {
+            graph,
+            changes,
+            watcher,
+        })
+    }
+
+    /// The graph, which is locked while changes are applied to it
+    pub fn graph(&self) -> &Arc<Mutex<DirectedHashSetGraph<TagGraphNode, Relation>>> {
+        &self.graph
+    }
+
+    /// The changes made to the graph, in the order they were made
+    pub fn changes(&self) -> &Receiver<GraphChange> {
+        &self.changes
+    }
+
+    /// Stops watching, waiting for an update in progress to finish
+    pub fn stop(self) {
+        self.watcher.stop();
+    }
+}
+
+/// Reads one tag file and applies its tags to the directory or files it belongs to.
+/// The tag file's path should be absolute, like the paths in the graph.
+fn add_tagfile_to_graph(
+    tag_graph: &mut HashSetGraph<TagGraphNode, Relation, Directed>,
+    tag_root: NodeIndex,
+    tagfile: &Path,
+    options: &ScanOptions,
+) -> Result<(), Error> {
+    if let Some(dir) = tagfile.parent() {
+        // Only added so the directory comes before what its tag files tag
+        let _ = tag_graph.get_node_move(TagGraphNode::Directory {
+            path: dir.to_path_buf(),
+        });
+    }
+    let targets = read_tagfile_targets(tagfile, options, &mut vec![])?;
+    apply_tagfile_targets(tag_graph, tag_root, tagfile, targets);
+    Ok(())
+}
+
+/// Gets the tags of everything under a directory, including the directory itself
+fn subtree_state(
+    tag_graph: &HashSetGraph<TagGraphNode, Relation, Directed>,
+    dir: &Path,
+) -> HashMap<TagGraphNode, BTreeSet<String>> {
+    tag_graph
+        .graph
+        .node_indices()
+        .filter(|&idx| {
+            tag_graph.graph[idx]
+                .path()
+                .is_some_and(|path| path.starts_with(dir))
+        })
+        .map(|idx| {
+            let tags = effective_tags(tag_graph, idx).into_iter().collect();
+            (tag_graph.graph[idx].clone(), tags)
+        })
+        .collect()
+}
+
+/// Works out the changes between two `subtree_state`s: removed nodes, added nodes, then lost and gained tags,
+/// each ordered by path
+fn state_changes(
+    before: HashMap<TagGraphNode, BTreeSet<String>>,
+    mut after: HashMap<TagGraphNode, BTreeSet<String>>,
+) -> Vec<GraphChange> {
+    let mut changes = vec![];
+    for (node, old_tags) in before {
+        let Some(new_tags) = after.remove(&node) else {
+            changes.push(GraphChange::NodeRemoved(node));
+            continue;
+        };
+        for tag in old_tags.difference(&new_tags) {
+            changes.push(GraphChange::TagDetached {
+                node: node.clone(),
+                tag: tag.clone(),
+            });
+        }
+        for tag in new_tags.difference(&old_tags) {
+            changes.push(GraphChange::TagAttached {
+                node: node.clone(),
+                tag: tag.clone(),
+            });
+        }
+    }
+    for (node, tags) in after {
+        changes.push(GraphChange::NodeAdded(node.clone()));
+        for tag in tags {
+            changes.push(GraphChange::TagAttached {
+                node: node.clone(),
+                tag,
+            });
+        }
+    }
+    changes.sort_by(|a, b| change_order(a).cmp(&change_order(b)));
+    changes
+}
+
+fn change_order(change: &GraphChange) -> (u8, Option<&Path>, Option<&str>) {
+    match change {
+        GraphChange::NodeRemoved(node) => (0, node.path(), None),
+        GraphChange::NodeAdded(node) => (1, node.path(), None),
+        GraphChange::TagDetached { node, tag } => (2, node.path(), Some(tag)),
+        GraphChange::TagAttached { node, tag } => (3, node.path(), Some(tag)),
+    }
+}
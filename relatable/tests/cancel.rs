@@ -0,0 +1,65 @@
+mod common;
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use relatable::{Error, ScanOptions};
+
+#[test]
+fn cancelled_before_starting() {
+    let dir = common::tree(&[("a.txt", ""), ("a.tags", "x\n")]);
+    let cancel = Arc::new(AtomicBool::new(true));
+
+    let result = relatable::scan(dir.path(), &ScanOptions::new().cancel(cancel));
+
+    assert!(
+        matches!(result, Err(Error::Cancelled)),
+        "{:?}",
+        result.map(|_| ())
+    );
+}
+
+/// The first tag file includes a named pipe, so the scan waits on it while another thread cancels it. The
+/// scan has to stop before reading the next tag file instead of going through the rest of the tree.
+#[cfg(unix)]
+#[test]
+fn cancelled_from_another_thread_mid_scan() {
+    use std::{fs::File, io::Write, process::Command};
+
+    let mut files = vec![("root/a/first.tags".to_string(), "@include ../../pipe\n")];
+    for i in 0..500 {
+        files.push((format!("root/b{}/file.txt", i), ""));
+        files.push((format!("root/b{}/file.tags", i), "tag\n"));
+    }
+    let files: Vec<(&str, &str)> = files.iter().map(|(p, c)| (p.as_str(), *c)).collect();
+    let dir = common::tree(&files);
+    let pipe = dir.path().join("pipe");
+    assert!(Command::new("mkfifo")
+        .arg(&pipe)
+        .status()
+        .unwrap()
+        .success());
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    let canceller = {
+        let cancel = cancel.clone();
+        std::thread::spawn(move || {
+            // Opening blocks until the scan opens the pipe to read it
+            let mut pipe = File::options().write(true).open(pipe).unwrap();
+            cancel.store(true, Ordering::Relaxed);
+            pipe.write_all(b"piped\n").unwrap();
+        })
+    };
+
+    let options = ScanOptions::new().threads(1).cancel(cancel);
+    let result = relatable::scan(dir.path().join("root"), &options);
+    canceller.join().unwrap();
+
+    assert!(
+        matches!(result, Err(Error::Cancelled)),
+        "{:?}",
+        result.map(|_| ())
+    );
+}
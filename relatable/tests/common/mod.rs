@@ -0,0 +1,26 @@
+#![allow(dead_code)]
+
+use std::{fs, path::Path};
+
+use tempfile::TempDir;
+
+/// Creates a directory tree from `(path, contents)` pairs, relative to a new temporary directory. Paths ending
+/// in `/` are created as empty directories.
+pub fn tree(files: &[(&str, &str)]) -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for (path, contents) in files {
+        let path = dir.path().join(path);
+        if path.to_string_lossy().ends_with('/') {
+            fs::create_dir_all(&path).unwrap();
+            continue;
+        }
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+    }
+    dir
+}
+
+/// Reads a file in a tree as a string
+pub fn read(root: &Path, path: &str) -> String {
+    fs::read_to_string(root.join(path)).unwrap()
+}
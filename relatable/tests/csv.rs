@@ -0,0 +1,52 @@
+mod common;
+
+use std::path::PathBuf;
+
+use relatable::{export::to_csv, import::from_csv, PathMode, ScanOptions};
+
+#[test]
+fn csv_round_trips_line_breaks_commas_and_quotes() {
+    let dir = common::tree(&[
+        ("line\nbreak.txt", ""),
+        ("line\nbreak.txt.tags", "a \"quoted\", tag\n"),
+        ("carriage\rreturn.txt", ""),
+        ("carriage\rreturn.txt.tags", "plain\n"),
+        ("crlf\r\nboth.txt", ""),
+        ("crlf\r\nboth.txt.tags", "plain\n"),
+    ]);
+    let options = ScanOptions::new().path_mode(PathMode::RelativeToRoot);
+    let (graph, _) = relatable::scan(dir.path(), &options).unwrap();
+
+    let mut csv = vec![];
+    to_csv(&graph, &mut csv, false).unwrap();
+    let mut rows = from_csv(csv.as_slice()).unwrap();
+    rows.sort();
+
+    assert_eq!(
+        rows,
+        [
+            (
+                PathBuf::from("carriage\rreturn.txt"),
+                vec!["plain".to_string()]
+            ),
+            (PathBuf::from("crlf\r\nboth.txt"), vec!["plain".to_string()]),
+            (
+                PathBuf::from("line\nbreak.txt"),
+                vec!["a \"quoted\", tag".to_string()]
+            ),
+        ]
+    );
+}
+
+#[test]
+fn from_csv_reads_quoted_line_breaks() {
+    let rows = from_csv("path,tag\r\n\"a\r\nb\",\"x\"\"y\"\r\nc,z\n".as_bytes()).unwrap();
+
+    assert_eq!(
+        rows,
+        [
+            (PathBuf::from("a\r\nb"), vec!["x\"y".to_string()]),
+            (PathBuf::from("c"), vec!["z".to_string()]),
+        ]
+    );
+}
@@ -0,0 +1,222 @@
+mod common;
+
+use relatable::{
+    edit::{add_tag, merge_tags, remove_tag, rename_tag},
+    Error, ScanOptions,
+};
+
+#[test]
+fn rename_tag_to_itself_changes_nothing() {
+    let dir = common::tree(&[("a", ""), ("a.tags", "wip\n# c\n")]);
+
+    let report = rename_tag(dir.path(), &ScanOptions::new(), "wip", "wip", false).unwrap();
+
+    assert_eq!(report.files_touched(), 0);
+    assert_eq!(common::read(dir.path(), "a.tags"), "wip\n# c\n");
+}
+
+#[test]
+fn merge_tag_into_itself_changes_nothing() {
+    let dir = common::tree(&[("a", ""), ("a.tags", "wip\n")]);
+    let (mut graph, _) = relatable::scan(dir.path(), &ScanOptions::new()).unwrap();
+
+    let report = merge_tags(
+        dir.path(),
+        &ScanOptions::new(),
+        "wip",
+        "wip",
+        Some(&mut graph),
+    )
+    .unwrap();
+
+    assert_eq!(report.files_touched(), 0);
+    assert_eq!(common::read(dir.path(), "a.tags"), "wip\n");
+    assert_eq!(
+        relatable::get_tags_for_path(&graph, &dir.path().join("a")),
+        ["wip"]
+    );
+}
+
+#[test]
+fn merge_tags_moves_child_tags() {
+    let dir = common::tree(&[
+        ("a", ""),
+        ("a.tags", "music/rock\nmusic\n"),
+        ("b", ""),
+        ("b.tags", "audio\nmusic/jazz\n"),
+    ]);
+    let (mut graph, _) = relatable::scan(dir.path(), &ScanOptions::new()).unwrap();
+
+    let report = merge_tags(
+        dir.path(),
+        &ScanOptions::new(),
+        "music",
+        "audio",
+        Some(&mut graph),
+    )
+    .unwrap();
+
+    assert_eq!(report.files_touched(), 2);
+    assert_eq!(common::read(dir.path(), "a.tags"), "audio/rock\naudio\n");
+    assert_eq!(common::read(dir.path(), "b.tags"), "audio\naudio/jazz\n");
+    assert_eq!(
+        relatable::get_tags_for_path(&graph, &dir.path().join("a")),
+        ["audio", "audio/rock"]
+    );
+    assert_eq!(
+        relatable::get_tags_for_path(&graph, &dir.path().join("b")),
+        ["audio", "audio/jazz"]
+    );
+    let tags = relatable::get_all_tags(&graph);
+    assert!(
+        tags.iter().all(|tag| !tag.starts_with("music")),
+        "{:?}",
+        tags
+    );
+
+    // The graph matches a fresh scan of the rewritten files
+    let (rescanned, _) = relatable::scan(dir.path(), &ScanOptions::new()).unwrap();
+    assert!(relatable::diff(&rescanned, &graph).is_empty());
+}
+
+#[test]
+fn rename_tag_rewrites_patterns_and_manifests() {
+    let dir = common::tree(&[
+        ("a.png", ""),
+        ("b.txt", ""),
+        ("_patterns.tags", "# screenshots\n*.png:  wip , done\r\n"),
+        ("manifest.tags.tsv", "b.txt\twip, -wip,in-progress\n"),
+    ]);
+
+    let report = rename_tag(dir.path(), &ScanOptions::new(), "wip", "in-progress", false).unwrap();
+
+    assert_eq!(report.files_touched(), 2);
+    assert_eq!(
+        common::read(dir.path(), "_patterns.tags"),
+        "# screenshots\n*.png:  in-progress , done\r\n"
+    );
+    assert_eq!(
+        common::read(dir.path(), "manifest.tags.tsv"),
+        "b.txt\t -in-progress,in-progress\n"
+    );
+    let (graph, _) = relatable::scan(dir.path(), &ScanOptions::new()).unwrap();
+    assert_eq!(
+        relatable::get_tags_for_path(&graph, &dir.path().join("a.png")),
+        ["done", "in-progress"]
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn rename_tag_rewrites_json_sidecars() {
+    let dir = common::tree(&[
+        ("a.txt", ""),
+        (
+            "a.txt.tags.json",
+            r#"{"tags": ["wip", "done"], "attrs": {"rating": 5}}"#,
+        ),
+        ("b.txt", ""),
+        ("b.txt.tags.json", r#"{"attrs": {"status": "wip"}}"#),
+    ]);
+
+    let report = rename_tag(dir.path(), &ScanOptions::new(), "wip", "in-progress", false).unwrap();
+    assert_eq!(report.files, [dir.path().join("a.txt.tags.json")]);
+    let (graph, _) = relatable::scan(dir.path(), &ScanOptions::new()).unwrap();
+    assert_eq!(
+        relatable::get_tags_for_path(&graph, &dir.path().join("a.txt")),
+        ["done", "in-progress", "rating:5"]
+    );
+
+    let report = rename_tag(
+        dir.path(),
+        &ScanOptions::new(),
+        "status:wip",
+        "status:done",
+        false,
+    )
+    .unwrap();
+    assert!(report.files.is_empty());
+    assert_eq!(report.skipped, [dir.path().join("b.txt.tags.json")]);
+}
+
+#[test]
+fn edits_use_the_tagfile_extension() {
+    let dir = common::tree(&[("a.txt", ""), ("b/", ""), ("b/dir.mytags", "wip\n")]);
+    let options = ScanOptions::new().tagfile_extension("mytags");
+    let (mut graph, _) = relatable::scan(dir.path(), &options).unwrap();
+
+    add_tag(
+        dir.path(),
+        &options,
+        &dir.path().join("a.txt"),
+        "wip",
+        Some(&mut graph),
+    )
+    .unwrap();
+    assert_eq!(common::read(dir.path(), "a.mytags"), "wip\n");
+    assert!(!dir.path().join("a.tags").exists());
+
+    let report = rename_tag(dir.path(), &options, "wip", "done", false).unwrap();
+    assert_eq!(
+        report.files,
+        [dir.path().join("a.mytags"), dir.path().join("b/dir.mytags")]
+    );
+    assert!(!remove_tag(&dir.path().join("a.txt"), "wip", false, &options).unwrap());
+    assert!(remove_tag(&dir.path().join("a.txt"), "done", false, &options).unwrap());
+    assert!(!dir.path().join("a.mytags").exists());
+}
+
+#[test]
+fn edits_reject_tags_the_parser_would_reject() {
+    let dir = common::tree(&[("a.txt", ""), ("b.txt", ""), ("b.tags", "wip\n")]);
+    let a = dir.path().join("a.txt");
+
+    for tag in ["/", "//", "a\tb", "a\u{7f}b"] {
+        let result = add_tag(dir.path(), &ScanOptions::new(), &a, tag, None);
+        assert!(
+            matches!(result, Err(Error::InvalidTag { .. })),
+            "{:?} gave {:?}",
+            tag,
+            result
+        );
+        let result = rename_tag(dir.path(), &ScanOptions::new(), "wip", tag, false);
+        assert!(matches!(result, Err(Error::InvalidTag { .. })), "{:?}", tag);
+    }
+    assert!(!dir.path().join("a.tags").exists());
+    assert_eq!(common::read(dir.path(), "b.tags"), "wip\n");
+
+    // Tags with an empty segment still have a name, so they're written, and read back without warnings
+    add_tag(dir.path(), &ScanOptions::new(), &a, "a//b", None).unwrap();
+    assert_eq!(common::read(dir.path(), "a.tags"), "a//b\n");
+    let (_, report) = relatable::scan(dir.path(), &ScanOptions::new()).unwrap();
+    assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+}
+
+#[test]
+fn add_tag_removes_its_exclusion() {
+    let dir = common::tree(&[
+        ("dir.tags", "wip\n"),
+        ("a.txt", ""),
+        ("a.tags", "-wip\n# keep\nother\n"),
+        ("b.txt", ""),
+        ("b.tags", "!wip\n"),
+    ]);
+    let (mut graph, _) = relatable::scan(dir.path(), &ScanOptions::new()).unwrap();
+    let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+    let wip = |graph: &_| relatable::files_with_tag(graph, "wip", true);
+    assert!(!wip(&graph).contains(&a));
+
+    add_tag(dir.path(), &ScanOptions::new(), &a, "wip", Some(&mut graph)).unwrap();
+    add_tag(dir.path(), &ScanOptions::new(), &b, "wip", Some(&mut graph)).unwrap();
+
+    assert_eq!(common::read(dir.path(), "a.tags"), "# keep\nother\nwip\n");
+    assert_eq!(common::read(dir.path(), "b.tags"), "wip\n");
+    assert!(wip(&graph).contains(&a));
+    assert!(wip(&graph).contains(&b));
+    assert_eq!(
+        relatable::files_with_tag(&graph, "wip", false),
+        [dir.path().to_path_buf(), a, b]
+    );
+    let (rescanned, _) = relatable::scan(dir.path(), &ScanOptions::new()).unwrap();
+    assert_eq!(wip(&graph), wip(&rescanned));
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use relatable::{read_tagfile, Error, ScanOptions};
+
+#[test]
+fn errors_name_the_path() {
+    let dir = common::tree(&[("file.txt", ""), ("bad.tags", "ok\n# fine\nglob:[\n")]);
+
+    let missing = dir.path().join("missing.tags");
+    let error = read_tagfile(&missing).unwrap_err();
+    assert!(matches!(error, Error::TagfileRead { .. }), "{:?}", error);
+    assert!(
+        error.to_string().contains(&format!("{:?}", missing)),
+        "{}",
+        error
+    );
+
+    let bad = dir.path().join("bad.tags");
+    let error = read_tagfile(&bad).unwrap_err();
+    assert!(
+        matches!(error, Error::TagfileParseError { line: 3, .. }),
+        "{:?}",
+        error
+    );
+    assert!(
+        error.to_string().contains(&format!("line 3 of {:?}", bad)),
+        "{}",
+        error
+    );
+
+    let file = dir.path().join("file.txt");
+    let error = relatable::scan(&file, &ScanOptions::new()).unwrap_err();
+    assert!(matches!(error, Error::NotADirectory { .. }), "{:?}", error);
+    assert!(
+        error.to_string().contains(&format!("{:?}", file)),
+        "{}",
+        error
+    );
+
+    let gone = dir.path().join("gone");
+    let error = relatable::scan(&gone, &ScanOptions::new()).unwrap_err();
+    assert!(
+        error.to_string().contains(&format!("{:?}", gone)),
+        "{}",
+        error
+    );
+}
@@ -0,0 +1,102 @@
+#![cfg(feature = "exif")]
+
+mod common;
+
+use std::fs;
+
+use relatable::{add_exif_tags_to_graph, ScanOptions};
+
+/// Builds a little-endian TIFF header and IFD with ASCII fields
+fn tiff(fields: &[(u16, &str)]) -> Vec<u8> {
+    let mut bytes = b"II*\0".to_vec();
+    bytes.extend(8u32.to_le_bytes());
+    bytes.extend((fields.len() as u16).to_le_bytes());
+    // The strings go after the entries and the offset of the next IFD
+    let mut data_offset = 8 + 2 + 12 * fields.len() as u32 + 4;
+    let mut data = vec![];
+    for (tag, value) in fields {
+        let mut value = value.as_bytes().to_vec();
+        value.push(0);
+        bytes.extend(tag.to_le_bytes());
+        bytes.extend(2u16.to_le_bytes());
+        bytes.extend((value.len() as u32).to_le_bytes());
+        if value.len() <= 4 {
+            // Short values are stored in place of the offset
+            value.resize(4, 0);
+            bytes.extend(value);
+        } else {
+            bytes.extend(data_offset.to_le_bytes());
+            data_offset += value.len() as u32;
+            data.extend(value);
+        }
+    }
+    bytes.extend(0u32.to_le_bytes());
+    bytes.extend(data);
+    bytes
+}
+
+fn xmp(keywords: &[&str]) -> String {
+    let items: String = keywords
+        .iter()
+        .map(|k| format!("<rdf:li>{}</rdf:li>", k))
+        .collect();
+    format!(
+        "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF><rdf:Description>\
+         <dc:subject><rdf:Bag>{}</rdf:Bag></dc:subject>\
+         </rdf:Description></rdf:RDF></x:xmpmeta>",
+        items
+    )
+}
+
+/// Wraps a TIFF and an XMP packet in the APP1 segments of a JPEG
+fn jpeg(tiff: &[u8], xmp: &str) -> Vec<u8> {
+    let mut bytes = vec![0xff, 0xd8];
+    for (header, payload) in [
+        (&b"Exif\0\0"[..], tiff),
+        (&b"http://ns.adobe.com/xap/1.0/\0"[..], xmp.as_bytes()),
+    ] {
+        bytes.extend([0xff, 0xe1]);
+        bytes.extend(((2 + header.len() + payload.len()) as u16).to_be_bytes());
+        bytes.extend(header);
+        bytes.extend(payload);
+    }
+    bytes.extend([0xff, 0xd9]);
+    bytes
+}
+
+#[test]
+fn exif_fields_and_xmp_keywords_become_tags() {
+    let dir = common::tree(&[("notes.txt", ""), ("empty.jpg", "")]);
+    let image_description = 0x10e;
+    let artist = 0x13b;
+    let copyright = 0x8298;
+    fs::write(
+        dir.path().join("beach.JPG"),
+        jpeg(
+            &tiff(&[
+                (image_description, "Sunset  at\nthe beach"),
+                (artist, "Ann"),
+            ]),
+            &xmp(&["holiday", "fish &amp; chips"]),
+        ),
+    )
+    .unwrap();
+    fs::write(dir.path().join("scan.tif"), tiff(&[(copyright, "Bob")])).unwrap();
+    let (mut graph, _) = relatable::scan(dir.path(), &ScanOptions::new()).unwrap();
+
+    add_exif_tags_to_graph(dir.path(), &mut graph).unwrap();
+
+    let tags_of = |name: &str| relatable::get_tags_for_path(&graph, &dir.path().join(name));
+    assert_eq!(
+        tags_of("beach.JPG"),
+        [
+            "artist:Ann",
+            "description:Sunset at the beach",
+            "fish & chips",
+            "holiday"
+        ]
+    );
+    assert_eq!(tags_of("scan.tif"), ["copyright:Bob"]);
+    assert!(tags_of("empty.jpg").is_empty());
+    assert!(tags_of("notes.txt").is_empty());
+}
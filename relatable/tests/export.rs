@@ -0,0 +1,135 @@
+mod common;
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    env, fs,
+    path::Path,
+};
+
+use relatable::{
+    export::{to_gexf, to_graphml, to_mermaid},
+    PathMode, ScanOptions, TagGraphNode,
+};
+use xml::reader::{EventReader, XmlEvent};
+
+/// Parses a document with a strict XML parser, getting the attributes of each element by name, in order.
+/// Text is added to the element it's in as a `#text` attribute.
+fn parse(document: &str) -> Vec<(String, HashMap<String, String>)> {
+    let mut elements: Vec<(String, HashMap<String, String>)> = vec![];
+    let mut open = vec![];
+    for event in EventReader::from_str(document) {
+        match event.unwrap() {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                open.push(elements.len());
+                let attributes = attributes
+                    .into_iter()
+                    .map(|a| (a.name.local_name, a.value))
+                    .collect();
+                elements.push((name.local_name, attributes));
+            }
+            XmlEvent::EndElement { .. } => {
+                open.pop();
+            }
+            XmlEvent::Characters(text) => {
+                let element = *open.last().unwrap();
+                elements[element].1.insert("#text".to_string(), text);
+            }
+            _ => {}
+        }
+    }
+    elements
+}
+
+fn tricky_graph() -> (
+    tempfile::TempDir,
+    relatable::DirectedHashSetGraph<relatable::TagGraphNode, relatable::Relation>,
+) {
+    let dir = common::tree(&[
+        ("fish & chips <\"hot\">.txt", ""),
+        (
+            "fish & chips <\"hot\">.tags",
+            "a&b\n<tag>\n\"quoted\"\nit's\n",
+        ),
+    ]);
+    let (graph, _) = relatable::scan(dir.path(), &ScanOptions::new()).unwrap();
+    (dir, graph)
+}
+
+/// Checks that every edge's ends are node ids
+fn assert_edges_connect_nodes(elements: &[(String, HashMap<String, String>)]) {
+    let ids: BTreeSet<&String> = elements
+        .iter()
+        .filter(|(name, _)| name == "node")
+        .map(|(_, attributes)| &attributes["id"])
+        .collect();
+    let edges: Vec<_> = elements.iter().filter(|(name, _)| name == "edge").collect();
+    assert!(!edges.is_empty());
+    for (_, attributes) in edges {
+        assert!(ids.contains(&attributes["source"]), "{:?}", attributes);
+        assert!(ids.contains(&attributes["target"]), "{:?}", attributes);
+    }
+}
+
+#[test]
+fn graphml_is_well_formed() {
+    let (dir, graph) = tricky_graph();
+
+    let elements = parse(&to_graphml(&graph));
+
+    assert_eq!(elements[0].0, "graphml");
+    assert_edges_connect_nodes(&elements);
+    let data: BTreeSet<&str> = elements
+        .iter()
+        .filter(|(name, _)| name == "data")
+        .filter_map(|(_, attributes)| attributes.get("#text").map(String::as_str))
+        .collect();
+    for label in ["[a&b]", "[<tag>]", "[\"quoted\"]", "[it's]"] {
+        assert!(data.contains(label), "{} isn't in {:?}", label, data);
+    }
+    let path = dir.path().join("fish & chips <\"hot\">.txt");
+    assert!(data.contains(path.to_str().unwrap()));
+}
+
+#[test]
+fn gexf_is_well_formed() {
+    let (_dir, graph) = tricky_graph();
+
+    let elements = parse(&to_gexf(&graph));
+
+    assert_eq!(elements[0].0, "gexf");
+    assert_edges_connect_nodes(&elements);
+    let labels: BTreeSet<&str> = elements
+        .iter()
+        .filter(|(name, _)| name == "node")
+        .map(|(_, attributes)| attributes["label"].as_str())
+        .collect();
+    for label in [
+        "[a&b]",
+        "[<tag>]",
+        "[\"quoted\"]",
+        "[it's]",
+        "fish & chips <\"hot\">.txt",
+    ] {
+        assert!(labels.contains(label), "{} isn't in {:?}", label, labels);
+    }
+}
+
+/// Compares the Mermaid neighborhood of the `first` tag in `testdata/` with `golden/testdata.mmd`. Run with
+/// `UPDATE_GOLDEN=1` to rewrite the golden file after an intended change.
+#[test]
+fn mermaid_matches_golden_file() {
+    let testdata = Path::new(env!("CARGO_MANIFEST_DIR")).join("../testdata");
+    let options = ScanOptions::new().path_mode(PathMode::RelativeToRoot);
+    let (mut graph, _) = relatable::scan(&testdata, &options).unwrap();
+    let focus = graph.get_node(&TagGraphNode::Tag("first".to_string()));
+
+    let mermaid = to_mermaid(&graph, focus, 2, 50);
+
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/testdata.mmd");
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&golden, &mermaid).unwrap();
+    }
+    assert_eq!(mermaid, fs::read_to_string(&golden).unwrap());
+}
@@ -0,0 +1,155 @@
+mod common;
+
+use std::collections::HashSet;
+
+use relatable::{DirectedHashSetGraph, Relation, ScanOptions, TagGraphNode};
+
+fn tag(name: &str) -> TagGraphNode {
+    TagGraphNode::Tag(name.to_string())
+}
+
+/// Builds a graph of `(from, relation, to)` edges between tags
+fn tag_graph(edges: &[(&str, Relation, &str)]) -> DirectedHashSetGraph<TagGraphNode, Relation> {
+    let mut graph = DirectedHashSetGraph::new();
+    for (from, relation, to) in edges {
+        let (a, b) = (graph.get_node(&tag(from)), graph.get_node(&tag(to)));
+        graph.graph.add_edge(a, b, relation.clone());
+    }
+    graph
+}
+
+#[test]
+fn merge_counts_overlapping_nodes_and_edges_once() {
+    let mut graph = tag_graph(&[
+        ("a", Relation::ChildTag, "b"),
+        ("b", Relation::ParentTag, "a"),
+    ]);
+    let other = tag_graph(&[
+        ("a", Relation::ChildTag, "b"),
+        ("b", Relation::ChildTag, "c"),
+        ("a", Relation::Alias, "b"),
+    ]);
+
+    graph.merge(other);
+
+    assert_eq!(graph.node_count(), 3);
+    assert_eq!(graph.edge_count(), 4);
+    assert_eq!(graph.edges_between(&tag("a"), &tag("b")).count(), 2);
+    // Merged nodes are found by weight, not added again
+    let _ = graph.get_node(&tag("c"));
+    assert_eq!(graph.node_count(), 3);
+}
+
+#[test]
+fn merge_from_overlapping_scans_keeps_one_node_per_file() {
+    let dir = common::tree(&[
+        ("a.txt", ""),
+        ("sub/b.txt", ""),
+        ("sub/b.tags", "inner\n"),
+        ("sub/deeper/c.txt", ""),
+    ]);
+    let (mut graph, _) = relatable::scan(dir.path(), &ScanOptions::new()).unwrap();
+    let (sub, _) = relatable::scan(dir.path().join("sub"), &ScanOptions::new()).unwrap();
+    let b = TagGraphNode::File {
+        path: dir.path().join("sub/b.txt"),
+    };
+    let b_index = graph.get_node(&b);
+
+    graph.merge_from(&sub);
+
+    let files: Vec<&TagGraphNode> = graph
+        .iter_nodes()
+        .filter(|node| matches!(node, TagGraphNode::File { .. }))
+        .collect();
+    assert_eq!(files.len(), 3);
+    assert_eq!(files.iter().collect::<HashSet<_>>().len(), 3);
+    assert_eq!(graph.get_node(&b), b_index);
+    assert_eq!(graph.edges_between(&b, &tag("inner")).count(), 1);
+}
+
+#[test]
+fn strongly_connected_components_find_cycles() {
+    let graph = tag_graph(&[
+        ("a", Relation::HasTag, "b"),
+        ("b", Relation::HasTag, "c"),
+        ("c", Relation::HasTag, "a"),
+        ("c", Relation::HasTag, "d"),
+        ("d", Relation::HasTag, "e"),
+        ("e", Relation::HasTag, "d"),
+        ("e", Relation::HasTag, "f"),
+    ]);
+
+    let mut components: Vec<Vec<TagGraphNode>> = graph
+        .strongly_connected_components()
+        .into_iter()
+        .map(|mut component| {
+            component.sort();
+            component
+        })
+        .collect();
+    // Reverse topological order: nothing in a component points at the ones before it
+    assert_eq!(components.first(), Some(&vec![tag("f")]));
+    assert_eq!(components.last(), Some(&vec![tag("a"), tag("b"), tag("c")]));
+
+    components.sort();
+    assert_eq!(
+        components,
+        [
+            vec![tag("a"), tag("b"), tag("c")],
+            vec![tag("d"), tag("e")],
+            vec![tag("f")],
+        ]
+    );
+}
+
+#[test]
+fn clones_are_independent() {
+    let mut graph = tag_graph(&[("a", Relation::ChildTag, "b")]);
+    let mut clone = graph.clone();
+
+    let (b, c) = (clone.get_node(&tag("b")), clone.get_node(&tag("c")));
+    clone.graph.add_edge(b, c, Relation::ChildTag);
+    assert!(clone.remove_node(&tag("a")));
+
+    assert_eq!(graph.node_count(), 2);
+    assert_eq!(graph.edge_count(), 1);
+    assert!(graph.contains_node(&tag("a")));
+    assert!(!graph.contains_node(&tag("c")));
+    assert_eq!(graph.edges_between(&tag("a"), &tag("b")).count(), 1);
+
+    let d = graph.get_node(&tag("d"));
+    let a = graph.get_node(&tag("a"));
+    graph.graph.add_edge(a, d, Relation::ChildTag);
+    assert!(graph.remove_node(&tag("b")));
+
+    assert_eq!(clone.node_count(), 2);
+    assert_eq!(clone.edge_count(), 1);
+    assert!(clone.contains_node(&tag("b")));
+    assert!(!clone.contains_node(&tag("a")));
+    assert!(!clone.contains_node(&tag("d")));
+    assert_eq!(clone.edges_between(&tag("b"), &tag("c")).count(), 1);
+    // The clone's map still finds its own nodes, not indices reused by the original
+    assert_eq!(clone.get_node(&tag("c")), c);
+    assert_eq!(clone.node_count(), 2);
+}
+
+#[test]
+fn weights_are_read_from_the_graph_after_editing_it_directly() {
+    let mut graph = tag_graph(&[("a", Relation::ChildTag, "b")]);
+    let a = graph.get_node(&tag("a"));
+    let c = graph.graph.add_node(tag("c"));
+    graph.graph.add_edge(a, c, Relation::ChildTag);
+
+    assert_eq!(graph.weight_of(c), Some(&tag("c")));
+    assert!(graph
+        .iter_edges()
+        .any(|(from, to, _)| *from == tag("a") && *to == tag("c")));
+    let neighbors: HashSet<_> = graph.neighbors(&tag("a")).collect();
+    assert_eq!(neighbors, HashSet::from([&tag("b"), &tag("c")]));
+    assert_eq!(
+        graph
+            .neighbors_directed(&tag("a"), petgraph::Direction::Outgoing)
+            .count(),
+        2
+    );
+}
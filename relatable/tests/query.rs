@@ -0,0 +1,126 @@
+mod common;
+
+use std::path::PathBuf;
+
+use relatable::{
+    files_with_attr, files_with_tag, get_all_tags,
+    range::{files_with_attr_range, parse_attr_value, unparseable_attr_values},
+    values_of, PathMode, ScanOptions,
+};
+
+#[test]
+fn files_with_tag_follows_dir_tags_and_sidecars() {
+    let dir = common::tree(&[
+        ("photos/dir.tags", "trip\n"),
+        ("photos/a.jpg", ""),
+        ("photos/b.jpg", ""),
+        ("photos/b.tags", "-trip\n"),
+        ("photos/nested/c.jpg", ""),
+        ("notes.txt", ""),
+        ("notes.tags", "trip\nwork\n"),
+    ]);
+    let options = ScanOptions::new().path_mode(PathMode::RelativeToRoot);
+    let (graph, _) = relatable::scan(dir.path(), &options).unwrap();
+    let paths = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+
+    assert_eq!(
+        files_with_tag(&graph, "trip", false),
+        paths(&["notes.txt", "photos"])
+    );
+    // Contained paths that opted out of the tag are left out
+    assert_eq!(
+        files_with_tag(&graph, "trip", true),
+        paths(&[
+            "notes.txt",
+            "photos",
+            "photos/a.jpg",
+            "photos/nested",
+            "photos/nested/c.jpg"
+        ])
+    );
+    assert_eq!(files_with_tag(&graph, "work", true), paths(&["notes.txt"]));
+    assert!(files_with_tag(&graph, "unknown", true).is_empty());
+}
+
+#[test]
+fn files_with_attr_range_compares_values_as_numbers() {
+    let dir = common::tree(&[
+        ("bond.txt", ""),
+        ("bond.tags", "rating:007\n"),
+        ("cold.txt", ""),
+        ("cold.tags", "rating:-3\n"),
+        ("half.txt", ""),
+        ("half.tags", "rating:2.5\n"),
+        ("five.txt", ""),
+        ("five.tags", "rating:5\n"),
+        ("good.txt", ""),
+        ("good.tags", "rating:good\n"),
+        ("later.txt", ""),
+        ("later.tags", "rating:tbd\n"),
+    ]);
+    let options = ScanOptions::new().path_mode(PathMode::RelativeToRoot);
+    let (graph, _) = relatable::scan(dir.path(), &options).unwrap();
+    let paths = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+
+    assert_eq!(parse_attr_value("007"), Some(7.0));
+    assert_eq!(parse_attr_value("-3"), Some(-3.0));
+    assert_eq!(parse_attr_value("inf"), None);
+    assert_eq!(unparseable_attr_values(&graph, "rating"), ["good", "tbd"]);
+
+    // Both ends of an inclusive range are in it
+    assert_eq!(
+        files_with_attr_range(&graph, "rating", 2.5..=7.0),
+        paths(&["bond.txt", "five.txt", "half.txt"])
+    );
+    assert_eq!(
+        files_with_attr_range(&graph, "rating", 2.5..7.0),
+        paths(&["five.txt", "half.txt"])
+    );
+    assert_eq!(
+        files_with_attr_range(&graph, "rating", -5..=-3),
+        paths(&["cold.txt"])
+    );
+    assert_eq!(
+        files_with_attr_range(&graph, "rating", ..0),
+        paths(&["cold.txt"])
+    );
+    // Values that aren't numbers are never in a range
+    assert_eq!(
+        files_with_attr_range::<f64, _>(&graph, "rating", ..).len(),
+        4
+    );
+}
+
+#[test]
+fn escaped_colons_stay_out_of_namespaces() {
+    let dir = common::tree(&[
+        ("time.txt", ""),
+        ("time.tags", "12\\:30\nat:12\\:30\n"),
+        ("namespaced.txt", ""),
+        ("namespaced.tags", "12:30\nat:12:30\n"),
+    ]);
+    let options = ScanOptions::new().path_mode(PathMode::RelativeToRoot);
+    let (graph, _) = relatable::scan(dir.path(), &options).unwrap();
+    let paths = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+
+    assert_eq!(values_of(&graph, "12"), ["30"]);
+    assert_eq!(
+        files_with_attr(&graph, "12", "30"),
+        paths(&["namespaced.txt"])
+    );
+    assert_eq!(
+        files_with_tag(&graph, "12\\:30", false),
+        paths(&["time.txt"])
+    );
+    assert_eq!(
+        files_with_tag(&graph, "12:30", false),
+        paths(&["namespaced.txt"])
+    );
+    assert!(get_all_tags(&graph).contains(&"12\\:30".to_string()));
+    // Colons in a value are the same whether they're escaped or not
+    assert_eq!(values_of(&graph, "at"), ["12:30"]);
+    assert_eq!(
+        files_with_attr(&graph, "at", "12:30"),
+        paths(&["namespaced.txt", "time.txt"])
+    );
+}
@@ -0,0 +1,204 @@
+mod common;
+
+use relatable::{
+    export::{to_dot, DotOptions},
+    get_tags_for_path, ScanOptions, ScanWarning,
+};
+
+#[test]
+fn overlapping_patterns_tag_the_same_file() {
+    let dir = common::tree(&[
+        (
+            "shots/_patterns.tags",
+            "*.png: screenshot, image\nshot-*: screenshot, raw\n*.gif: animated\n",
+        ),
+        ("shots/shot-1.png", ""),
+        ("shots/other.png", ""),
+        ("shots/shot-2.jpg", ""),
+    ]);
+
+    let (graph, report) = relatable::scan(dir.path(), &ScanOptions::new()).unwrap();
+
+    let tags_of = |name: &str| get_tags_for_path(&graph, &dir.path().join("shots").join(name));
+    assert_eq!(tags_of("shot-1.png"), ["image", "raw", "screenshot"]);
+    assert_eq!(tags_of("other.png"), ["image", "screenshot"]);
+    assert_eq!(tags_of("shot-2.jpg"), ["raw", "screenshot"]);
+    // The tag file itself isn't matched
+    assert!(tags_of("_patterns.tags").is_empty());
+    assert!(report.warnings.iter().any(|warning| matches!(
+        warning,
+        ScanWarning::UnmatchedPattern { pattern, .. } if pattern == "*.gif"
+    )));
+}
+
+/// Checks that a scan skips the part of the tree it can't read with a warning, and that `fail_fast` makes
+/// it fail instead
+#[cfg(unix)]
+fn assert_walk_error_is_reported(
+    dir: &std::path::Path,
+    unreadable: &std::path::Path,
+    options: ScanOptions,
+) {
+    let (graph, report) = relatable::scan(dir, &options).unwrap();
+    assert!(
+        report.warnings.iter().any(|warning| matches!(
+            warning,
+            ScanWarning::WalkError { path, .. } if path == unreadable
+        )),
+        "{:?}",
+        report.warnings
+    );
+    assert_eq!(get_tags_for_path(&graph, &dir.join("ok.txt")), ["fine"]);
+
+    assert!(relatable::scan(dir, &options.fail_fast(true)).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn unreadable_directories_are_reported() {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    let dir = common::tree(&[("ok.txt", ""), ("ok.tags", "fine\n"), ("locked/a.txt", "")]);
+    let locked = dir.path().join("locked");
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+    // Permissions don't stop root
+    let readable = fs::read_dir(&locked).is_ok();
+    if !readable {
+        assert_walk_error_is_reported(dir.path(), &locked, ScanOptions::new());
+        assert_walk_error_is_reported(dir.path(), &locked, ScanOptions::new().use_gitignore(true));
+    }
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn broken_links_being_followed_are_reported() {
+    let dir = common::tree(&[("ok.txt", ""), ("ok.tags", "fine\n")]);
+    let broken = dir.path().join("broken");
+    std::os::unix::fs::symlink("missing", &broken).unwrap();
+
+    let options = ScanOptions::new().follow_symlinks(true);
+    assert_walk_error_is_reported(dir.path(), &broken, options.clone());
+    assert_walk_error_is_reported(dir.path(), &broken, options.use_gitignore(true));
+}
+
+/// Checks that a scan skips a tag file it can't parse with a warning, and that `fail_fast` makes it fail instead
+fn assert_skipped_tagfile_is_reported(files: &[(&str, &str)], skipped: &str) {
+    let dir = common::tree(files);
+    let skipped = dir.path().join(skipped);
+    let (_, report) = relatable::scan(dir.path(), &ScanOptions::new()).unwrap();
+    assert!(
+        report.warnings.iter().any(|warning| matches!(
+            warning,
+            ScanWarning::SkippedTagfile { path, .. } if *path == skipped
+        )),
+        "{:?}",
+        report.warnings
+    );
+
+    assert!(relatable::scan(dir.path(), &ScanOptions::new().fail_fast(true)).is_err());
+}
+
+#[test]
+fn unparsable_tagfiles_are_reported() {
+    assert_skipped_tagfile_is_reported(&[("a.txt", ""), ("a.tags", "fine\n/\n")], "a.tags");
+    #[cfg(feature = "serde")]
+    assert_skipped_tagfile_is_reported(
+        &[("a.txt", ""), ("a.txt.tags.json", "{\"tags\": [")],
+        "a.txt.tags.json",
+    );
+}
+
+#[test]
+fn roots_with_glob_characters_are_scanned() {
+    // `?` and `*` aren't allowed in Windows file names
+    let name = if cfg!(windows) {
+        "music [flac]"
+    } else {
+        "music [flac] ? *"
+    };
+    let dir = common::tree(&[
+        (&format!("{}/dir.tags", name), "music\n"),
+        (&format!("{}/a.flac", name), ""),
+        (&format!("{}/a.tags", name), "favourite\n"),
+        (&format!("{}/_patterns.tags", name), "*.flac: lossless\n"),
+    ]);
+    let root = dir.path().join(name);
+
+    let (graph, report) = relatable::scan(&root, &ScanOptions::new()).unwrap();
+
+    assert_eq!(report.tagfiles, 3);
+    assert_eq!(
+        get_tags_for_path(&graph, &root.join("a.flac")),
+        ["favourite", "lossless", "music"]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_names_are_tagged() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let dir = common::tree(&[("dir.tags", "inherited\n"), (".tags", "not-a-tag\n")]);
+    let file = dir.path().join(OsStr::from_bytes(b"caf\xe9.txt"));
+    std::fs::write(&file, "").unwrap();
+    std::fs::write(
+        dir.path().join(OsStr::from_bytes(b"caf\xe9.tags")),
+        "latin1\n",
+    )
+    .unwrap();
+
+    let options = ScanOptions::new().include_hidden(true);
+    let (graph, _) = relatable::scan(dir.path(), &options).unwrap();
+
+    assert!(graph.contains_node(&relatable::TagGraphNode::File { path: file.clone() }));
+    assert_eq!(get_tags_for_path(&graph, &file), ["inherited", "latin1"]);
+    // A file named just `.tags` is a hidden file to tag, not a tag file
+    let dot_tags = dir.path().join(".tags");
+    assert!(graph.contains_node(&relatable::TagGraphNode::File {
+        path: dot_tags.clone()
+    }));
+    assert_eq!(get_tags_for_path(&graph, &dot_tags), ["inherited"]);
+}
+
+#[test]
+fn gitignore_only_changes_what_it_ignores() {
+    let files = [
+        ("dir.tags", "root\n"),
+        ("src/main.rs", ""),
+        ("src/main.tags", "code\n"),
+        ("build/out.bin", ""),
+        ("build/dir.tags", "generated\n"),
+        ("build/out.tags", "binary\n"),
+        ("notes/_patterns.tags", "*.md: notes\n"),
+        ("notes/a.md", ""),
+    ];
+    let dump = |dir: &std::path::Path, options: &ScanOptions| {
+        to_dot(
+            &relatable::scan(dir, options).unwrap().0,
+            &DotOptions::default(),
+        )
+    };
+
+    // Without ignore files the two walks give the same graph, byte for byte
+    let plain = common::tree(&files);
+    assert_eq!(
+        dump(plain.path(), &ScanOptions::new().use_gitignore(true)),
+        dump(plain.path(), &ScanOptions::new())
+    );
+
+    let mut ignoring_files = files.to_vec();
+    ignoring_files.push((".gitignore", "build/\n"));
+    let ignoring = common::tree(&ignoring_files);
+    let (graph, _) = relatable::scan(ignoring.path(), &ScanOptions::new()).unwrap();
+    assert_eq!(
+        get_tags_for_path(&graph, &ignoring.path().join("build/out.bin")),
+        ["binary", "generated", "root"]
+    );
+    let (graph, _) =
+        relatable::scan(ignoring.path(), &ScanOptions::new().use_gitignore(true)).unwrap();
+    assert!(!graph.iter_nodes().any(|node| node
+        .path()
+        .is_some_and(|path| path.starts_with(ignoring.path().join("build")))));
+    assert_eq!(relatable::get_all_tags(&graph), ["code", "notes", "root"]);
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "serde")]
+
+mod common;
+
+use relatable::{DirectedHashSetGraph, Relation, ScanOptions, TagGraphNode};
+
+#[test]
+fn serde_round_trips() {
+    let dir = common::tree(&[
+        ("photos/dir.tags", "trip/beach\n"),
+        ("photos/a.jpg", ""),
+        ("photos/a.tags", "rating:5\n-trip/beach\n"),
+        ("notes.txt", ""),
+    ]);
+    let (graph, _) = relatable::scan(dir.path(), &ScanOptions::new()).unwrap();
+
+    let json = serde_json::to_string(&graph).unwrap();
+    let loaded: DirectedHashSetGraph<TagGraphNode, Relation> = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(loaded.graph.node_count(), graph.graph.node_count());
+    assert_eq!(loaded.graph.edge_count(), graph.graph.edge_count());
+    assert!(relatable::diff(&graph, &loaded).is_empty());
+
+    let duplicated = r#"{"nodes": [{"Tag": "a"}, {"Tag": "a"}], "edges": []}"#;
+    let error = serde_json::from_str::<DirectedHashSetGraph<TagGraphNode, Relation>>(duplicated)
+        .unwrap_err();
+    assert!(error.to_string().contains("more than once"), "{}", error);
+}
+
+#[test]
+fn get_node_finds_deserialized_nodes() {
+    let dir = common::tree(&[("a.txt", ""), ("a.tags", "beach\n")]);
+    let (graph, _) = relatable::scan(dir.path(), &ScanOptions::new()).unwrap();
+    let json = serde_json::to_string(&graph).unwrap();
+
+    let mut loaded: DirectedHashSetGraph<TagGraphNode, Relation> =
+        serde_json::from_str(&json).unwrap();
+
+    let (nodes, edges) = (loaded.node_count(), loaded.edge_count());
+    for node in graph.iter_nodes() {
+        let idx = loaded.get_node(node);
+        assert_eq!(loaded.weight_of(idx), Some(node));
+    }
+    assert_eq!(loaded.node_count(), nodes);
+    assert_eq!(loaded.edge_count(), edges);
+    assert_eq!(
+        relatable::get_tags_for_path(&loaded, &dir.path().join("a.txt")),
+        ["beach"]
+    );
+}
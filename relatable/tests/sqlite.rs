@@ -0,0 +1,30 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use relatable::{add_virtual_tags_by_extension, get_tagged_files_from_db, save_to_db, ScanOptions};
+
+#[test]
+fn save_to_db_round_trips() {
+    let dir = common::tree(&[
+        ("photos/dir.tags", "trip/beach\n"),
+        ("photos/a.jpg", ""),
+        ("photos/a.jpg.tags", "rating:5\n-trip/beach\n"),
+        ("notes.md", ""),
+    ]);
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("photos/a.jpg", dir.path().join("link")).unwrap();
+    let (mut graph, _) = relatable::scan(dir.path(), &ScanOptions::new()).unwrap();
+    add_virtual_tags_by_extension(&mut graph, dir.path()).unwrap();
+    let db = dir.path().join("graph.db");
+
+    save_to_db(&graph, &db).unwrap();
+    let loaded = get_tagged_files_from_db(&db).unwrap();
+    assert!(relatable::diff(&graph, &loaded).is_empty());
+    assert_eq!(loaded.graph.edge_count(), graph.graph.edge_count());
+
+    // Saving again replaces what was there
+    save_to_db(&loaded, &db).unwrap();
+    let reloaded = get_tagged_files_from_db(&db).unwrap();
+    assert!(relatable::diff(&graph, &reloaded).is_empty());
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use std::path::Path;
+
+use relatable::{
+    tagfile::{write_contents_atomic, TagfileDocument},
+    ScanOptions, TagOperation,
+};
+
+#[test]
+fn leftover_tmp_is_ignored_and_replaced() {
+    let dir = common::tree(&[
+        ("a", ""),
+        ("a.tags", "wip\n"),
+        ("a.tags.tmp", "half-written\n"),
+    ]);
+
+    let (graph, _) = relatable::scan(dir.path(), &ScanOptions::new()).unwrap();
+    assert_eq!(relatable::get_all_tags(&graph), ["wip"]);
+    assert_eq!(
+        relatable::get_tags_for_path(&graph, &dir.path().join("a")),
+        ["wip"]
+    );
+
+    write_contents_atomic(&dir.path().join("a.tags"), b"done\n", false).unwrap();
+    assert_eq!(common::read(dir.path(), "a.tags"), "done\n");
+    assert!(!dir.path().join("a.tags.tmp").exists());
+}
+
+#[test]
+fn unchanged_document_saves_byte_identical() {
+    let cases = [
+        "# comment\nwip\n\n\\#hash\n-old\nglob:*.jpg\n",
+        "\u{feff}beach\r\n# c\r\n\\-dash\r\nlast",
+        "  spaced  \n\n\n",
+    ];
+    for contents in cases {
+        let dir = common::tree(&[("a.tags", contents)]);
+        let path = dir.path().join("a.tags");
+
+        let mut document = TagfileDocument::open(&path).unwrap();
+        assert!(!document.edit_operations(|_| None));
+        assert!(!document.remove_operation(&TagOperation::Add("missing".to_string())));
+        document.save(&path, false).unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), contents.as_bytes());
+    }
+}
+
+#[test]
+fn comments_blank_lines_and_escaped_hashes() {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/comments.tags");
+
+    let operations = relatable::read_tagfile(&fixture).unwrap();
+
+    assert_eq!(
+        operations,
+        ["beach", "#hashtag", "sunset # not a comment"]
+            .map(|tag| TagOperation::Add(tag.to_string()))
+    );
+
+    // Blank lines don't make an empty tag
+    let contents = std::fs::read_to_string(&fixture).unwrap();
+    let dir = common::tree(&[("a.jpg", ""), ("a.tags", &contents)]);
+    let (graph, _) = relatable::scan(dir.path(), &ScanOptions::new()).unwrap();
+    assert_eq!(
+        relatable::get_all_tags(&graph),
+        ["#hashtag", "beach", "sunset # not a comment"]
+    );
+}
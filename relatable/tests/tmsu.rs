@@ -0,0 +1,69 @@
+#![cfg(feature = "tmsu")]
+
+mod common;
+
+use std::path::PathBuf;
+
+use relatable::{
+    import::{apply, from_tmsu, WriteMode},
+    ScanOptions,
+};
+use rusqlite::Connection;
+
+/// Creates a database with TMSU's tables
+fn tmsu_db(path: &std::path::Path) -> Connection {
+    let connection = Connection::open(path).unwrap();
+    connection
+        .execute_batch(
+            "CREATE TABLE tag (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+             CREATE TABLE value (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+             CREATE TABLE file (id INTEGER PRIMARY KEY, directory TEXT NOT NULL, name TEXT NOT NULL,
+                 fingerprint TEXT NOT NULL, mod_time DATETIME NOT NULL, size INTEGER NOT NULL,
+                 is_dir BOOLEAN NOT NULL);
+             CREATE TABLE file_tag (file_id INTEGER NOT NULL, tag_id INTEGER NOT NULL,
+                 value_id INTEGER NOT NULL, PRIMARY KEY (file_id, tag_id, value_id));",
+        )
+        .unwrap();
+    connection
+}
+
+#[test]
+fn from_tmsu_reads_tags_and_values() {
+    let dir = common::tree(&[("photos/a.jpg", ""), ("photos/b.jpg", ""), (".tmsu/", "")]);
+    let db = dir.path().join(".tmsu/db");
+    tmsu_db(&db)
+        .execute_batch(
+            "INSERT INTO tag VALUES (1, 'beach'), (2, 'year'), (3, 'a:b');
+             INSERT INTO value VALUES (1, '2017');
+             INSERT INTO file VALUES (1, 'photos', 'a.jpg', '', 0, 0, 0),
+                 (2, 'photos', 'b.jpg', '', 0, 0, 0), (3, 'photos', 'gone.jpg', '', 0, 0, 0);
+             INSERT INTO file_tag VALUES (1, 1, 0), (1, 2, 1), (2, 3, 0), (3, 1, 0);",
+        )
+        .unwrap();
+
+    let assignments = from_tmsu(&db).unwrap();
+    assert_eq!(
+        assignments,
+        [
+            (
+                PathBuf::from("photos/a.jpg"),
+                vec!["beach".to_string(), "year:2017".to_string()]
+            ),
+            (PathBuf::from("photos/b.jpg"), vec!["a\\:b".to_string()]),
+            (PathBuf::from("photos/gone.jpg"), vec!["beach".to_string()]),
+        ]
+    );
+
+    let options = ScanOptions::new();
+    let report = apply(dir.path(), &options, &assignments, WriteMode::Append).unwrap();
+    assert_eq!(report.tagfiles_written, 2);
+    assert_eq!(report.missing, [dir.path().join("photos/gone.jpg")]);
+    assert_eq!(
+        common::read(dir.path(), "photos/a.tags"),
+        "beach\nyear:2017\n"
+    );
+
+    // Importing again changes nothing
+    let report = apply(dir.path(), &options, &assignments, WriteMode::Append).unwrap();
+    assert_eq!(report.tagfiles_written, 0);
+}
@@ -0,0 +1,100 @@
+#![cfg(feature = "watch")]
+
+mod common;
+
+use std::{
+    fs,
+    sync::{mpsc, Arc, Mutex},
+    time::Duration,
+};
+
+use relatable::{
+    watch::{get_tagged_files_with_metadata, rebuild_changed, watch, GraphChange, TagWatcher},
+    ScanOptions, TagGraphNode,
+};
+
+#[test]
+fn rebuild_changed_uses_the_tagfile_extension() {
+    let dir = common::tree(&[("a.txt", ""), ("b.txt", ""), ("a.mytags", "red\n")]);
+    let options = ScanOptions::new().tagfile_extension("mytags");
+    let (mut graph, mut meta) = get_tagged_files_with_metadata(dir.path(), &options).unwrap();
+
+    fs::write(dir.path().join("b.mytags"), "blue\n").unwrap();
+    fs::write(dir.path().join("b.tags"), "green\n").unwrap();
+    let updated = rebuild_changed(dir.path(), &options, &mut graph, &mut meta).unwrap();
+
+    assert!(updated > 0);
+    assert_eq!(
+        relatable::get_tags_for_path(&graph, &dir.path().join("b.txt")),
+        ["blue"]
+    );
+    assert!(meta.tagfiles.contains_key(&dir.path().join("b.mytags")));
+    assert!(!meta.tagfiles.contains_key(&dir.path().join("b.tags")));
+}
+
+#[test]
+fn watch_applies_changed_tagfiles() {
+    let dir = common::tree(&[("a.txt", ""), ("a.tags", "red\n"), ("sub/b.txt", "")]);
+    let root = dir.path().canonicalize().unwrap();
+    let (graph, _) = relatable::scan(&root, &ScanOptions::new()).unwrap();
+    let graph = Arc::new(Mutex::new(graph));
+    let (sender, updates) = mpsc::channel();
+    let watcher = watch(&root, ScanOptions::new(), graph.clone(), move || {
+        let _ = sender.send(());
+    })
+    .unwrap();
+
+    fs::write(root.join("a.tags"), "blue\n").unwrap();
+    fs::write(root.join("sub/dir.tags"), "green\n").unwrap();
+    let tags_of =
+        |path: &str| relatable::get_tags_for_path(&graph.lock().unwrap(), &root.join(path));
+    while tags_of("a.txt") != ["blue"] || tags_of("sub/b.txt") != ["green"] {
+        updates.recv_timeout(Duration::from_secs(10)).unwrap();
+    }
+
+    // Moving a directory takes its tag file with it
+    fs::rename(root.join("sub"), root.join("moved")).unwrap();
+    while !tags_of("sub/b.txt").is_empty() {
+        updates.recv_timeout(Duration::from_secs(10)).unwrap();
+    }
+    watcher.stop();
+}
+
+#[test]
+fn tag_watcher_reports_changes() {
+    let dir = common::tree(&[("a.txt", "")]);
+    let root = dir.path().canonicalize().unwrap();
+    let watcher = TagWatcher::new(&root, ScanOptions::new()).unwrap();
+
+    fs::write(root.join("a.tags"), "red\n").unwrap();
+    let attached = GraphChange::TagAttached {
+        node: TagGraphNode::File {
+            path: root.join("a.txt"),
+        },
+        tag: "red".to_string(),
+    };
+    loop {
+        let change = watcher
+            .changes()
+            .recv_timeout(Duration::from_secs(10))
+            .unwrap();
+        if change == attached {
+            break;
+        }
+    }
+
+    fs::write(root.join("b.txt"), "").unwrap();
+    let added = GraphChange::NodeAdded(TagGraphNode::File {
+        path: root.join("b.txt"),
+    });
+    loop {
+        let change = watcher
+            .changes()
+            .recv_timeout(Duration::from_secs(10))
+            .unwrap();
+        if change == added {
+            break;
+        }
+    }
+    watcher.stop();
+}